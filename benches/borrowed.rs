@@ -0,0 +1,41 @@
+use card_format::borrowed::parse_cards_ref;
+use card_format::parse_cards;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+/// A generated deck where most values are text, which is where borrowing saves the most
+fn text_deck(n: usize) -> String {
+    let mut s = String::from("@param cost kind\n@def 0 unit:\n.text:\"Does nothing much at all\"\n");
+    for i in 0..n {
+        s.push_str(&format!(
+            "card{} {} soldier:\n.text:\"Card number {} strikes for {} damage\"\n.tags:[red,green,blue]\n.stats:{{hp:{},at:{}}}\n",
+            i,
+            i % 7,
+            i,
+            i % 5,
+            i % 11,
+            i % 3
+        ));
+    }
+    s
+}
+
+fn bench_borrowed(c: &mut Criterion) {
+    let mut g = c.benchmark_group("owned_vs_borrowed");
+    for n in [1_000, 100_000].iter().copied() {
+        let s = text_deck(n);
+        g.bench_with_input(BenchmarkId::new("parse_cards", n), &s, |b, s| {
+            b.iter(|| parse_cards(black_box(s)).unwrap())
+        });
+        g.bench_with_input(BenchmarkId::new("parse_cards_ref", n), &s, |b, s| {
+            b.iter(|| parse_cards_ref(black_box(s)).unwrap())
+        });
+    }
+    g.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_borrowed
+}
+criterion_main!(benches);
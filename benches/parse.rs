@@ -0,0 +1,81 @@
+//! Parsing benchmarks. The 1M card deck takes several seconds per run, filter with eg
+//! `cargo bench --bench parse --features util -- 10000` to skip it.
+use card_format::cli::{self, Format};
+use card_format::parse::LineParser;
+use card_format::tokenize::CardTokenizer;
+use card_format::{parse_cards, Card};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+/// A generated deck using params, consts, and deep "*", "." and "$" paths on every card
+fn deep_deck(n: usize) -> String {
+    let mut s = String::from(
+        "@param cost kind\n@const base:{hp:1,moves:[[1,2]]}\n@def 0 unit:\n.text:\"Nothing special\"\n",
+    );
+    for i in 0..n {
+        s.push_str(&format!(
+            "{}*card{} {} soldier:\n.jobs:[[wood,{}]]\n.jobs.*:stone\n.jobs*:[iron,2]\n.g$s$t:deep\n.stats:$base\n.stats$moves.*:{}\n",
+            i % 3 + 1,
+            i,
+            i % 9,
+            i % 4,
+            i % 6
+        ));
+    }
+    s
+}
+
+/// The same conversion card_check uses for json output
+fn to_json(cards: Vec<Card>) -> String {
+    cli::convert(cards, Format::Json).unwrap()
+}
+
+fn bench_tokenizer(c: &mut Criterion) {
+    let s = deep_deck(10_000);
+    let mut g = c.benchmark_group("tokenizer");
+    g.throughput(Throughput::Bytes(s.len() as u64));
+    g.bench_function("next_10000_cards", |b| {
+        b.iter(|| {
+            let mut tk = CardTokenizer::new(black_box(&s));
+            let mut n = 0;
+            while let Some(t) = tk.next().unwrap() {
+                black_box(t);
+                n += 1;
+            }
+            n
+        })
+    });
+    g.finish();
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut g = c.benchmark_group("parse_cards");
+    g.sample_size(10);
+    for n in [10, 10_000, 1_000_000].iter().copied() {
+        let s = deep_deck(n);
+        g.throughput(Throughput::Elements(n as u64));
+        g.bench_with_input(BenchmarkId::from_parameter(n), &s, |b, s| {
+            b.iter(|| LineParser::new(black_box(s)).parse_cards().unwrap())
+        });
+    }
+    g.finish();
+}
+
+fn bench_json(c: &mut Criterion) {
+    let mut g = c.benchmark_group("json");
+    g.sample_size(10);
+    for n in [10, 10_000].iter().copied() {
+        let cards = parse_cards(&deep_deck(n)).unwrap();
+        g.throughput(Throughput::Elements(n as u64));
+        g.bench_with_input(BenchmarkId::new("convert", n), &cards, |b, cards| {
+            b.iter(|| to_json(black_box(cards.clone())))
+        });
+        let s = deep_deck(n);
+        g.bench_with_input(BenchmarkId::new("parse_and_convert", n), &s, |b, s| {
+            b.iter(|| to_json(parse_cards(black_box(s)).unwrap()))
+        });
+    }
+    g.finish();
+}
+
+criterion_group!(benches, bench_tokenizer, bench_parse, bench_json);
+criterion_main!(benches);
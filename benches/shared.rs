@@ -0,0 +1,102 @@
+use card_format::{parse_cards, CData, Card};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts the bytes allocated, to compare memory use
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, l: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(l.size(), Ordering::Relaxed);
+        System.alloc(l)
+    }
+    unsafe fn dealloc(&self, p: *mut u8, l: Layout) {
+        ALLOCATED.fetch_sub(l.size(), Ordering::Relaxed);
+        System.dealloc(p, l)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// A deck where every card uses one large const and inherits one large default
+fn const_deck(n: usize) -> String {
+    let items: Vec<String> = (0..200).map(|i| format!("{{id:{},name:item{}}}", i, i)).collect();
+    let mut s = format!("@const table:[{}]\n@def\n.rules:$table\n", items.join(","));
+    for i in 0..n {
+        s.push_str(&format!("card{}:\n.uses:$table\n", i));
+    }
+    s
+}
+
+/// CData as it was before lists and maps were shared, every card holding its own copy
+#[derive(Clone)]
+#[allow(dead_code)]
+enum PlainData {
+    Leaf(CData),
+    L(Vec<PlainData>),
+    M(BTreeMap<String, PlainData>),
+}
+
+#[derive(Clone)]
+#[allow(dead_code)]
+struct PlainCard {
+    num: usize,
+    name: String,
+    data: BTreeMap<String, PlainData>,
+    tags: BTreeSet<String>,
+}
+
+fn plain(d: &CData) -> PlainData {
+    match d {
+        CData::L(l) => PlainData::L(l.iter().map(plain).collect()),
+        CData::M(m) => PlainData::M(m.iter().map(|(k, v)| (k.clone(), plain(v))).collect()),
+        d => PlainData::Leaf(d.clone()),
+    }
+}
+
+fn plain_cards(cards: &[Card]) -> Vec<PlainCard> {
+    cards
+        .iter()
+        .map(|c| PlainCard {
+            num: c.num,
+            name: c.name.clone(),
+            data: c.data.iter().map(|(k, v)| (k.clone(), plain(v))).collect(),
+            tags: c.tags.clone(),
+        })
+        .collect()
+}
+
+fn held_by<T, F: FnOnce() -> T>(f: F) -> (T, usize) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let res = f();
+    (res, ALLOCATED.load(Ordering::Relaxed) - before)
+}
+
+fn bench_shared(c: &mut Criterion) {
+    let s = const_deck(2_000);
+    let (cards, shared) = held_by(|| parse_cards(&s).unwrap());
+    let (copied, unshared) = held_by(|| plain_cards(&cards));
+    println!(
+        "2000 cards: {} KiB held with sharing, {} KiB without",
+        shared / 1024,
+        unshared / 1024
+    );
+
+    let mut g = c.benchmark_group("shared_values");
+    g.bench_function("parse_shared", |b| b.iter(|| parse_cards(black_box(&s)).unwrap()));
+    g.bench_function("clone_shared", |b| b.iter(|| black_box(&cards).clone()));
+    g.bench_function("clone_unshared", |b| b.iter(|| black_box(&copied).clone()));
+    g.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_shared
+}
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+#![no_main]
+use card_format::borrowed::{parse_cards_ref, CardRef};
+use card_format::parse_cards;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|s: &str| {
+    let owned = parse_cards(s);
+    let borrowed = parse_cards_ref(s);
+    if let Ok(cards) = owned {
+        // Anything that parses prints back to the same cards
+        let printed: String = cards.iter().map(|c| c.to_string()).collect();
+        assert_eq!(parse_cards(&printed).unwrap(), cards);
+        if let Ok(b) = borrowed {
+            let b: Vec<_> = b.into_iter().map(CardRef::into_owned).collect();
+            assert_eq!(b, cards);
+        }
+    }
+});
@@ -0,0 +1,9 @@
+#![no_main]
+use card_format::tokenize::CardTokenizer;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|s: &str| {
+    for mut tk in [CardTokenizer::new(s), CardTokenizer::borrowing(s)] {
+        while let Ok(Some(_)) = tk.next() {}
+    }
+});
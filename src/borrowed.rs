@@ -0,0 +1,263 @@
+//! A parse mode whose cards borrow their text from the source, for loading very large decks.
+//! It is LineParser building CardRefs, so reads the same syntax with the same options, except for "@include".
+use crate::card::{CDPathNode, CData, Card};
+use crate::dice::Dice;
+use crate::err::CardErr;
+use crate::interval::Interval;
+use crate::options::ParserOptions;
+use crate::parse::{LineParser, ParsedCard, ParsedData};
+use crate::CardRes;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+pub type RefMap<'a> = BTreeMap<Cow<'a, str>, CDataRef<'a>>;
+
+/// As CData, but strings point into the source where they can
+#[derive(Debug, PartialEq, Clone)]
+pub enum CDataRef<'a> {
+    S(Cow<'a, str>),
+    N(i128),
+    L(Vec<CDataRef<'a>>),
+    M(RefMap<'a>),
+    R(Cow<'a, str>),
+    D(Dice),
+    I(Interval),
+}
+
+impl<'a> CDataRef<'a> {
+    pub fn into_owned(self) -> CData {
+        match self {
+            CDataRef::S(s) => CData::S(s.into_owned()),
+            CDataRef::N(n) => CData::N(n),
+            CDataRef::L(l) => CData::L(Arc::new(l.into_iter().map(CDataRef::into_owned).collect())),
+            CDataRef::M(m) => CData::M(Arc::new(
+                m.into_iter()
+                    .map(|(k, v)| (k.into_owned(), v.into_owned()))
+                    .collect(),
+            )),
+            CDataRef::R(r) => CData::R(r.into_owned()),
+            CDataRef::D(d) => CData::D(d),
+            CDataRef::I(i) => CData::I(i),
+        }
+    }
+
+    /// How many lists and maps deep the value goes, 0 for a plain value
+    pub fn depth(&self) -> usize {
+        match self {
+            CDataRef::L(l) => 1 + l.iter().map(CDataRef::depth).max().unwrap_or(0),
+            CDataRef::M(m) => 1 + m.values().map(CDataRef::depth).max().unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            CDataRef::S(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn add_at_path(&mut self, c: CDataRef<'a>, path: &[CDPathNode]) -> Result<(), CardErr> {
+        let rest = path.get(1..).unwrap_or_default();
+        match (self, path.first()) {
+            (CDataRef::L(l), Some(CDPathNode::DigLast)) => match l.last_mut() {
+                Some(ls) => return ls.add_at_path(c, rest),
+                None => l.push(Self::build_from_path(c, rest)),
+            },
+            (CDataRef::L(l), _) => l.push(Self::build_from_path(c, rest)),
+            (CDataRef::M(m), Some(CDPathNode::AtKey(k))) => match m.get_mut(k.as_str()) {
+                Some(v) => v.add_at_path(c, rest)?,
+                None => {
+                    m.insert(Cow::Owned(k.clone()), Self::build_from_path(c, rest));
+                }
+            },
+            (_, _) => return Err(CardErr::S("Could not add child at path")),
+        }
+        Ok(())
+    }
+
+    pub fn build_from_path(c: CDataRef<'a>, path: &[CDPathNode]) -> CDataRef<'a> {
+        match path.first() {
+            Some(CDPathNode::AtKey(k)) => {
+                let mut mp = BTreeMap::new();
+                mp.insert(Cow::Owned(k.clone()), Self::build_from_path(c, &path[1..]));
+                CDataRef::M(mp)
+            }
+            Some(_) => CDataRef::L(vec![Self::build_from_path(c, &path[1..])]),
+            None => c,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CardRef<'a> {
+    pub num: usize,
+    pub name: Cow<'a, str>,
+    pub data: RefMap<'a>,
+    pub tags: Vec<Cow<'a, str>>,
+}
+
+impl<'a> CardRef<'a> {
+    pub fn fill_defaults(&mut self, rmap: &RefMap<'a>) {
+        for (k, v) in rmap {
+            if !self.data.contains_key(k) {
+                self.data.insert(k.clone(), v.clone());
+            }
+        }
+    }
+
+    pub fn into_owned(self) -> Card {
+        let data = self
+            .data
+            .into_iter()
+            .map(|(k, v)| (k.into_owned(), v.into_owned()))
+            .collect();
+        Card::build(self.name.into_owned(), self.num, data)
+            .with_tags(self.tags.into_iter().map(Cow::into_owned))
+    }
+}
+
+impl<'a> ParsedData<'a> for CDataRef<'a> {
+    type Str = Cow<'a, str>;
+    const BORROWS: bool = true;
+    fn from_owned(d: CData) -> Self {
+        match d {
+            CData::S(s) => CDataRef::S(Cow::Owned(s)),
+            CData::N(n) => CDataRef::N(n),
+            CData::L(l) => CDataRef::L(l.iter().cloned().map(Self::from_owned).collect()),
+            CData::M(m) => CDataRef::M(
+                m.iter()
+                    .map(|(k, v)| (Cow::Owned(k.clone()), Self::from_owned(v.clone())))
+                    .collect(),
+            ),
+            CData::R(r) => CDataRef::R(Cow::Owned(r)),
+            CData::D(d) => CDataRef::D(d),
+            CData::I(i) => CDataRef::I(i),
+        }
+    }
+    fn text(s: Cow<'a, str>) -> Self {
+        CDataRef::S(s)
+    }
+    fn card_ref(s: Cow<'a, str>) -> Self {
+        CDataRef::R(s)
+    }
+    fn number(n: i128) -> Self {
+        CDataRef::N(n)
+    }
+    fn dice(d: Dice) -> Self {
+        CDataRef::D(d)
+    }
+    fn interval(i: Interval) -> Self {
+        CDataRef::I(i)
+    }
+    fn list(l: Vec<Self>) -> Self {
+        CDataRef::L(l)
+    }
+    fn map(m: RefMap<'a>) -> Self {
+        CDataRef::M(m)
+    }
+    fn depth(&self) -> usize {
+        CDataRef::depth(self)
+    }
+    fn add_at_path(&mut self, c: Self, path: &[CDPathNode]) -> Result<(), CardErr> {
+        CDataRef::add_at_path(self, c, path)
+    }
+    fn build_from_path(c: Self, path: &[CDPathNode]) -> Self {
+        CDataRef::build_from_path(c, path)
+    }
+}
+
+impl<'a> ParsedCard<'a> for CardRef<'a> {
+    type Data = CDataRef<'a>;
+    fn build(num: usize, name: Cow<'a, str>, data: RefMap<'a>, tags: Vec<Cow<'a, str>>) -> Self {
+        CardRef { num, name, data, tags }
+    }
+    fn name(&self) -> &Cow<'a, str> {
+        &self.name
+    }
+    fn data_mut(&mut self) -> &mut RefMap<'a> {
+        &mut self.data
+    }
+    fn fill_defaults(&mut self, defaults: &RefMap<'a>) {
+        CardRef::fill_defaults(self, defaults)
+    }
+    fn merge(&mut self, later: Self) {
+        self.data.extend(later.data);
+        self.tags.extend(later.tags);
+    }
+    fn add_num(&mut self, later: Self) {
+        self.num += later.num;
+    }
+    /// The included file would not live as long as the cards borrowing from it
+    fn include(p: &mut RefParser<'a>, _: String) -> CardRes<()> {
+        Err(CardErr::S("Includes are not supported when borrowing").at(p.line_pos()))
+    }
+}
+
+/// A LineParser whose cards borrow their names, keys and strings from the source
+pub type RefParser<'a> = LineParser<'a, CardRef<'a>>;
+
+/// Parse cards that borrow their names, keys and strings from s
+pub fn parse_cards_ref(s: &str) -> CardRes<Vec<CardRef<'_>>> {
+    RefParser::building(s, ParserOptions::default()).parse_cards()
+}
+
+/// As parse_cards_ref, with the options
+pub fn parse_cards_ref_with<'a>(s: &'a str, opts: &ParserOptions) -> CardRes<Vec<CardRef<'a>>> {
+    RefParser::building(s, opts.clone()).parse_cards()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_cards;
+    #[test]
+    pub fn test_borrowed_matches_owned() {
+        let s = std::fs::read_to_string("test_data/cards3_maps.crd").unwrap();
+        let s = format!(
+            "{}\n@param cost\n@const g:{{a:[1,-2]}}\n2*\"Big Dog\" %pet 4:\n.text@fr:Chien\n.g$b:$g\n.up:&Cat\n.l:[[x]]\n.l.:y",
+            s
+        );
+        let borrowed = parse_cards_ref(&s).unwrap();
+        let dog = &borrowed[borrowed.len() - 1];
+        assert!(matches!(dog.name, Cow::Borrowed("Big Dog")));
+        assert!(matches!(dog.data.get("up"), Some(CDataRef::R(Cow::Borrowed("Cat")))));
+        let owned: Vec<Card> = borrowed.into_iter().map(CardRef::into_owned).collect();
+        let expect = parse_cards(&s).unwrap();
+        assert_eq!(owned.len(), expect.len());
+        for (a, b) in owned.iter().zip(&expect) {
+            assert_eq!((&a.name, a.num, &a.data, &a.tags), (&b.name, b.num, &b.data, &b.tags));
+        }
+    }
+    #[test]
+    pub fn test_borrowed_shares_options() {
+        use crate::parse::{DupPolicy, Parsed};
+        let s = "@param cost\n@section a\nArcher $silver 4:\n.text:a\n@section b\nArcher 2:\n.power:2\nMage:";
+        let opts = ParserOptions::new()
+            .strict_vars(false)
+            .strict_params(false)
+            .dups(DupPolicy::Merge)
+            .default_value("health", CData::N(1));
+        assert!(parse_cards_ref_with(&format!("{}\n.x:@", s), &opts).is_err());
+        let mut res = Parsed::default();
+        RefParser::building(s, opts.clone()).parse_into(&mut res).unwrap();
+        assert_eq!(res.warnings.len(), 2);
+        assert_eq!(res.sections.len(), 2);
+        assert_eq!(res.sections[0].0.name.as_deref(), Some("a"));
+        assert_eq!(res.sections[1].1[0].name, "Mage");
+        let archer = &res.sections[0].1[0];
+        assert_eq!(archer.data.get("cost"), Some(&CDataRef::N(2)));
+        assert_eq!(archer.data.get("power"), Some(&CDataRef::N(2)));
+        assert_eq!(archer.data.get("health"), Some(&CDataRef::N(1)));
+        let owned: Vec<Card> = res.cards().into_iter().map(CardRef::into_owned).collect();
+        let expect = opts.parse(s).unwrap();
+        assert_eq!(owned, expect);
+
+        let mut p = RefParser::building("A:\n.x:$y", ParserOptions::new().constant("y", CData::N(3)));
+        assert_eq!(p.next_card().unwrap().unwrap().data.get("x"), Some(&CDataRef::N(3)));
+        assert_eq!(p.var_refs()[0].0, "y");
+        let e = parse_cards_ref_with("@include \"x.crd\"", &ParserOptions::new().includes(true));
+        assert!(e.unwrap_err().to_string().contains("borrowing"));
+    }
+}
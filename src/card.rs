@@ -1,9 +1,15 @@
+use crate::dice::Dice;
+use crate::interval::Interval;
 use crate::err::CardErr;
+use crate::locale::split_lang;
+use crate::tokenize::{is_lang_char, quote, word};
 use serde_derive::*;
 use serde::Serializer as SS;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{self, Display};
-use serde::ser::{Serialize, Serializer, SerializeSeq, SerializeMap};
+use std::sync::Arc;
+use std::convert::TryFrom;
+use serde::ser::{SerializeSeq, SerializeMap};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum CDPathNode {
@@ -11,30 +17,48 @@ pub enum CDPathNode {
     Append,
     AtKey(String),
 }
+/// Lists and maps are shared, so using a "$const" or a default in many cards does not copy it.
+/// They are copied on write, by add_at_path for example.
 #[derive(Debug, PartialEq, Clone)]
 pub enum CData {
     S(String),
-    N(isize),
-    L(Vec<CData>),
-    M(BTreeMap<String, CData>),
+    N(i128),
+    L(Arc<Vec<CData>>),
+    M(Arc<BTreeMap<String, CData>>),
+    /// A reference to another card by name, written "&Name"
+    R(String),
+    /// A dice roll, written "2d6+1"
+    D(Dice),
+    /// A range of numbers including both ends, written "3..5", "..7" or "3.."
+    I(Interval),
 }
 
 impl serde::Serialize for CData {
     fn serialize<S:SS>(&self,ser: S) -> Result<<S as SS>::Ok,<S as SS>::Error> {
         match self {
             CData::S(s) => ser.serialize_str(s),
-            CData::N(n) => ser.serialize_i64(*n as i64),
+            CData::N(n) => match i64::try_from(*n) {
+                Ok(n) => ser.serialize_i64(n),
+                Err(_) => ser.serialize_u64(*n as u64),
+            },
+            CData::R(r) => {
+                let mut map = ser.serialize_map(Some(1))?;
+                map.serialize_entry("ref", r)?;
+                map.end()
+            }
+            CData::D(d) => d.serialize(ser),
+            CData::I(i) => i.serialize(ser),
             CData::L(l) => {
                 let mut seq = ser.serialize_seq(Some(l.len()))?;
-                for e in l {
-                    seq.serialize_element(e);
+                for e in l.iter() {
+                    seq.serialize_element(e)?;
                 }
                 seq.end()
             }
             CData::M(m) => {
                 let mut map = ser.serialize_map(Some(m.len()))?;
-                for (k,v) in m {
-                    map.serialize_entry(k,v);
+                for (k,v) in m.iter() {
+                    map.serialize_entry(k,v)?;
                 }
                 map.end()
             }
@@ -46,23 +70,28 @@ impl serde::Serialize for CData {
 impl Display for CData {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            CData::S(s) => write!(f, r#""{}""#, s),
+            CData::S(s) => write!(f, "{}", quote(s)),
             CData::N(n) => write!(f, "{}", n),
+            CData::R(r) => write!(f, "&{}", word(r)),
+            CData::D(d) => write!(f, "{}", d),
+            CData::I(i) => write!(f, "{}", i),
             CData::L(l) => {
-                let mut pre = "[";
-                for item in l {
+                write!(f, "[")?;
+                let mut pre = "";
+                for item in l.iter() {
                     write!(f, "{}{}", pre, item)?;
                     pre = ",";
                 }
                 write!(f, "]")
             }
             CData::M(m) => {
-                let mut pre = "{";
-                for (k, v) in m {
-                    write!(f, "{}{}:{}", pre, k, v)?;
+                write!(f, "{{")?;
+                let mut pre = "";
+                for (k, v) in m.iter() {
+                    write!(f, "{}{}:{}", pre, word(k), v)?;
                     pre = ",";
                 }
-                write!(f, "{}", "}")
+                write!(f, "}}")
             }
         }
     }
@@ -71,22 +100,23 @@ impl Display for CData {
 impl CData {
     pub fn wrap(mut self, w: usize) -> Self {
         for _ in 0..w {
-            self = CData::L(vec![self]);
+            self = CData::L(vec![self].into());
         }
         self
     }
 
     pub fn add_at_path(&mut self, c: CData, path: &[CDPathNode]) -> Result<(), CardErr> {
-        match (self, path.get(0)) {
-            (CData::L(l), Some(CDPathNode::DigLast)) => match l.last_mut() {
-                Some(ls) => return ls.add_at_path(c, &path[1..]),
-                None => l.push(Self::build_from_path(c, &path[1..])),
+        let rest = path.get(1..).unwrap_or_default();
+        match (self, path.first()) {
+            (CData::L(l), Some(CDPathNode::DigLast)) => match Arc::make_mut(l).last_mut() {
+                Some(ls) => return ls.add_at_path(c, rest),
+                None => Arc::make_mut(l).push(Self::build_from_path(c, rest)),
             },
-            (CData::L(l), _) => l.push(Self::build_from_path(c, &path[1..])),
-            (CData::M(m), Some(CDPathNode::AtKey(k))) => match m.get_mut(k) {
-                Some(v) => v.add_at_path(c, &path[1..])?,
+            (CData::L(l), _) => Arc::make_mut(l).push(Self::build_from_path(c, rest)),
+            (CData::M(m), Some(CDPathNode::AtKey(k))) => match Arc::make_mut(m).get_mut(k) {
+                Some(v) => v.add_at_path(c, rest)?,
                 None => {
-                    m.insert(k.clone(), CData::build_from_path(c, &path[1..]));
+                    Arc::make_mut(m).insert(k.clone(), CData::build_from_path(c, rest));
                 }
             },
             (_, _) => return Err(CardErr::S("Could not add child at path")),
@@ -95,13 +125,13 @@ impl CData {
     }
 
     pub fn build_from_path(c: CData, path: &[CDPathNode]) -> CData {
-        match path.get(0) {
+        match path.first() {
             Some(CDPathNode::AtKey(k)) => {
                 let mut mp = BTreeMap::new();
                 mp.insert(k.clone(), CData::build_from_path(c, &path[1..]));
-                CData::M(mp)
+                CData::M(mp.into())
             }
-            Some(_) => CData::L(vec![CData::build_from_path(c, &path[1..])]),
+            Some(_) => CData::L(vec![CData::build_from_path(c, &path[1..])].into()),
             None => c,
         }
     }
@@ -109,7 +139,8 @@ impl CData {
     pub fn add_child(&mut self, c: CData, depth: usize) -> Result<(), CardErr> {
         match self {
             CData::L(l) => {
-                if depth <= 0 {
+                let l = Arc::make_mut(l);
+                if depth == 0 {
                     l.push(c);
                     return Ok(());
                 }
@@ -125,19 +156,55 @@ impl CData {
         Ok(())
     }
 
+    /// How many lists and maps deep the value goes, 0 for a plain value
+    pub fn depth(&self) -> usize {
+        match self {
+            CData::L(l) => 1 + l.iter().map(CData::depth).max().unwrap_or(0),
+            CData::M(m) => 1 + m.values().map(CData::depth).max().unwrap_or(0),
+            _ => 0,
+        }
+    }
+
     pub fn as_list(&self) -> Option<&Vec<CData>> {
         match self {
             CData::L(l) => Some(l),
             _ => None,
         }
     }
+
+    pub fn as_dice(&self) -> Option<&Dice> {
+        match self {
+            CData::D(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    pub fn as_interval(&self) -> Option<&Interval> {
+        match self {
+            CData::I(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// The name of the card referred to, if this is a reference
+    pub fn as_ref_name(&self) -> Option<&str> {
+        match self {
+            CData::R(r) => Some(r),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Clone, Debug, Serialize)]
+/// Make cards with new or build and with_tags, rather than by listing the fields,
+/// so that code still builds when fields are added.
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct Card {
     pub num: usize,
     pub name: String,
     pub data: BTreeMap<String, CData>,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    /// Sets and other labels, written "%tag" in the card header
+    pub tags: BTreeSet<String>,
 }
 
 impl Card {
@@ -146,32 +213,186 @@ impl Card {
             name,
             num,
             data: BTreeMap::new(),
+            tags: BTreeSet::new(),
         }
     }
     pub fn build(name: String, num: usize, data: BTreeMap<String, CData>) -> Card {
-        Card { name, num, data }
+        Card {
+            name,
+            num,
+            data,
+            tags: BTreeSet::new(),
+        }
+    }
+
+    pub fn with_tags<I: IntoIterator<Item = String>>(mut self, tags: I) -> Card {
+        self.tags.extend(tags);
+        self
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    /// Find a value by a dotted path such as "jobs.0.1" or "g.s".
+    /// "name" and "num" give the card's own name and count.
+    pub fn lookup(&self, path: &str) -> Option<CData> {
+        let mut parts = path.split('.');
+        let first = parts.next()?;
+        let mut res = match first {
+            "name" if !self.data.contains_key("name") => return Some(CData::S(self.name.clone())),
+            "num" if !self.data.contains_key("num") => return Some(CData::N(self.num as i128)),
+            k => self.data.get(k)?,
+        };
+        for p in parts {
+            res = match res {
+                CData::M(m) => m.get(p)?,
+                CData::L(l) => l.get(p.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        Some(res.clone())
     }
 
     pub fn fill_defaults(&mut self, rmap: &BTreeMap<String, CData>) {
         for (k, v) in rmap {
-            if self.data.get(k).is_none() {
+            if !self.data.contains_key(k) {
                 self.data.insert(k.clone(), v.clone());
             }
         }
     }
+    /// A copy of the card using the "key@lang" variant of each property in place of the plain value.
+    /// Without a variant for lang, the fallback variant is used, then the plain value.
+    pub fn localized(&self, lang: &str, fallback: &str) -> Card {
+        let mut data = BTreeMap::new();
+        for k in self.data.keys() {
+            let base = k.split('@').next().unwrap_or(k);
+            if data.contains_key(base) {
+                continue;
+            }
+            let v = [format!("{}@{}", base, lang), format!("{}@{}", base, fallback), base.to_string()]
+                .iter()
+                .find_map(|k| self.data.get(k));
+            if let Some(v) = v {
+                data.insert(base.to_string(), v.clone());
+            }
+        }
+        Card {
+            num: self.num,
+            name: self.name.clone(),
+            data,
+            tags: self.tags.clone(),
+        }
+    }
+    /// The card as one map, with its header tags under "%tags" so they never replace a ".tags" property
     pub fn flatten(mut self)->CData{
         self.data.insert("name".to_string(), CData::S(self.name));
-        self.data.insert("num".to_string(), CData::N(self.num as isize));
-        CData::M(self.data)
+        self.data.insert("num".to_string(), CData::N(self.num as i128));
+        if !self.tags.is_empty() {
+            let tags: Vec<CData> = self.tags.into_iter().map(CData::S).collect();
+            self.data.insert("%tags".to_string(), CData::L(tags.into()));
+        }
+        CData::M(self.data.into())
     }
 }
 
 impl Display for Card {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f, "{}*{} : ", self.num, self.name)?;
+        write!(f, "{}*{} ", self.num, word(&self.name))?;
+        for t in &self.tags {
+            write!(f, "%{} ", word(t))?;
+        }
+        writeln!(f, ": ")?;
         for (k, v) in &self.data {
-            writeln!(f, ".{}:{}", k, v)?;
+            // keep "key@lang" readable when the language can follow the key unquoted
+            match split_lang(k) {
+                (base, Some(lang)) if !lang.is_empty() && lang.chars().all(is_lang_char) => {
+                    writeln!(f, ".{}@{}:{}", word(base), lang, v)?
+                }
+                _ => writeln!(f, ".{}:{}", word(k), v)?,
+            }
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::borrowed::{parse_cards_ref, CardRef};
+    use crate::parse_cards;
+    use crate::tokenize::{NUMBER_MAX, NUMBER_MIN};
+    use proptest::prelude::*;
+
+    fn text() -> impl Strategy<Value = String> {
+        prop_oneof![
+            "[a-zA-Z][a-zA-Z0-9_]{0,6}",
+            "[a-z0-9 _\"\\\\@%&$.,:;#\\[\\]{}*\n-]{0,8}",
+            any::<String>(),
+        ]
+    }
+
+    fn cdata() -> impl Strategy<Value = CData> {
+        let leaf = prop_oneof![
+            text().prop_map(CData::S),
+            (NUMBER_MIN..=NUMBER_MAX).prop_map(CData::N),
+            text().prop_map(CData::R),
+            (1..100u32, 1..100u32, -100..100isize).prop_map(|(c, s, m)| CData::D(Dice::new(c, s, m))),
+            (proptest::option::of(NUMBER_MIN..=NUMBER_MAX), proptest::option::of(NUMBER_MIN..=NUMBER_MAX))
+                .prop_filter_map("start after end", |(a, b)| Interval::new(a, b))
+                .prop_filter("no ends", |i| i.start.is_some() || i.end.is_some())
+                .prop_map(CData::I),
+        ];
+        leaf.prop_recursive(4, 32, 5, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..5).prop_map(|l| CData::L(l.into())),
+                prop::collection::btree_map(text(), inner, 0..5).prop_map(|m| CData::M(m.into())),
+            ]
+        })
+    }
+
+    fn card() -> impl Strategy<Value = Card> {
+        (
+            0..10000usize,
+            text(),
+            prop::collection::btree_map(text(), cdata(), 0..5),
+            prop::collection::btree_set(text(), 0..3),
+        )
+            .prop_map(|(num, name, data, tags)| Card::build(name, num, data).with_tags(tags))
+    }
+
+    proptest! {
+        #[test]
+        fn test_display_round_trip(cards in prop::collection::vec(card(), 0..4)) {
+            let s: String = cards.iter().map(|c| c.to_string()).collect();
+            prop_assert_eq!(&parse_cards(&s).unwrap(), &cards);
+            let borrowed: Vec<Card> = parse_cards_ref(&s).unwrap().into_iter().map(CardRef::into_owned).collect();
+            prop_assert_eq!(&borrowed, &cards);
+        }
+    }
+
+    #[test]
+    pub fn test_display_quotes_when_needed() {
+        let mut data = BTreeMap::new();
+        data.insert("text@fr".to_string(), CData::S("Say \"hi\" \\o/".to_string()));
+        data.insert("two words".to_string(), CData::R("Big Dog".to_string()));
+        data.insert("none".to_string(), CData::L(Vec::new().into()));
+        let c = Card::build("Dog".to_string(), 2, data).with_tags(vec!["pet".to_string(), "a b".to_string()]);
+        let s = c.to_string();
+        assert_eq!(
+            s,
+            "2*Dog %\"a b\" %pet : \n.none:[]\n.text@fr:\"Say \\\"hi\\\" \\\\o/\"\n.\"two words\":&\"Big Dog\"\n"
+        );
+        assert_eq!(parse_cards(&s).unwrap(), vec![c]);
+    }
+    #[test]
+    pub fn test_flatten_keeps_tags_property() {
+        let c = parse_cards("Dog %pet:\n.tags:[old]").unwrap().remove(0);
+        let m = match c.flatten() {
+            CData::M(m) => m,
+            _ => panic!("a card flattens to a map"),
+        };
+        assert_eq!(m.get("tags").unwrap().to_string(), "[\"old\"]");
+        assert_eq!(m.get("%tags").unwrap().to_string(), "[\"pet\"]");
+    }
+}
@@ -1,42 +1,376 @@
 extern crate card_format;
-use std::io::{self, Read};
-use clap::{Arg,arg,Command,crate_version};
-use card_format::card::{Card,CData};
+use std::io;
+use std::path::{Path, PathBuf};
+use clap::{arg,Arg,ArgMatches,Command,crate_version};
+use card_format::card::Card;
+use card_format::cli::{self, io_err, CheckErr, CheckRes, FilterOptions, Format, SimOptions, TableFormat};
+use card_format::html;
+use card_format::lint::{LintConfig, Rule};
+use card_format::parse::{DupPolicy, Parsed, Section};
+use card_format::render::Layout;
+use card_format::ParserOptions;
 
-pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// The --dups policy for repeated card names, and --includes
+fn parser_options(sub: &ArgMatches) -> ParserOptions {
+    let dups = sub
+        .get_one::<String>("dups")
+        .and_then(|d| DupPolicy::from_name(d))
+        .unwrap_or_default();
+    ParserOptions::new().dups(dups).includes(sub.get_flag("includes"))
+}
 
-    let matches = Command::new("card_check")
-        .version(crate_version!())
-        .about("A program to check and convert card_format from stdin to stdout")
-        .author("Matthew Stoodley (storyfeet)")
-        .subcommand(Command::new("json"))
-        .get_matches();
-  
+fn load_parsed(files: &[PathBuf], opts: &ParserOptions) -> CheckRes<Parsed> {
+    let res = cli::load_parsed(files, opts)?;
+    cli::write_warnings(&res, &mut io::stderr())?;
+    Ok(res)
+}
 
-    let mut buf = String::new();
-    let mut sin = io::stdin();
-    sin.read_to_string(&mut buf)?;
+fn load_file(sub: &ArgMatches, id: &str) -> CheckRes<Vec<Card>> {
+    Ok(load_parsed(&[PathBuf::from(sub.get_one::<String>(id).unwrap())], &parser_options(sub))?.cards())
+}
 
-    let ar = card_format::parse_cards(&buf)?;
+/// Read the files named in the args, or stdin if there are none
+fn load_input_sections(sub: &ArgMatches) -> CheckRes<Vec<(Section, Vec<Card>)>> {
+    let res = match sub.get_many::<String>("files") {
+        Some(f) => cli::load_parsed(&cli::expand_globs(f)?, &parser_options(sub))?,
+        None => cli::read_parsed(&mut io::stdin(), &parser_options(sub))
+            .map_err(|e| match e {
+                CheckErr::Io(e) => CheckErr::Io(format!("stdin: {}", e)),
+                e => e,
+            })?,
+    };
+    cli::write_warnings(&res, &mut io::stderr())?;
+    Ok(res.sections)
+}
 
-    match matches.subcommand() {
-        Some(("json",_)) => {
-            let mp:Vec<CData> = ar.into_iter().map(Card::flatten).collect();
-            
-            print!("{}", serde_json::to_string_pretty(&mp)?);
-        }
+fn load_input(sub: &ArgMatches) -> CheckRes<Vec<Card>> {
+    Ok(load_input_sections(sub)?.into_iter().flat_map(|(_, v)| v).collect())
+}
 
-        _ => for (i, c) in ar.iter().enumerate() {
-            println!("{} = {}", i, c);
+/// Run f on the --output file, or stdout
+fn with_output<F: FnOnce(&mut dyn io::Write) -> CheckRes<T>, T>(sub: &ArgMatches, f: F) -> CheckRes<T> {
+    match sub.get_one::<String>("output") {
+        Some(o) => {
+            let mut buf = Vec::new();
+            let res = f(&mut buf)?;
+            std::fs::write(o, buf).map_err(io_err(Path::new(o)))?;
+            Ok(res)
         }
+        None => f(&mut io::stdout().lock()),
     }
+}
 
+fn format(sub: &ArgMatches) -> Format {
+    sub.get_one::<String>("format")
+        .and_then(|f| Format::from_name(f))
+        .unwrap_or_default()
+}
 
+fn strings(sub: &ArgMatches, id: &str) -> Vec<String> {
+    sub.get_many::<String>(id).into_iter().flatten().cloned().collect()
+}
 
-    
-
-    Ok(())
+fn input_args(c: Command) -> Command {
+    c.arg(
+        Arg::new("files")
+            .num_args(1..)
+            .help("Card files or glob patterns, read as one deck (default stdin)"),
+    )
+    .arg(arg!(-o --output <file> "Write to this file instead of stdout"))
 }
 
+fn run() -> CheckRes<()> {
+    let matches = input_args(Command::new("card_check"))
+        .version(crate_version!())
+        .about("A program to check and convert card_format files, or stdin, to stdout")
+        .author("Matthew Stoodley (storyfeet)")
+        .args_conflicts_with_subcommands(true)
+        .arg(
+            arg!(--dups <policy> "What to do with cards named the same as an earlier card")
+                .value_parser(["allow", "error", "warn", "merge", "sum"])
+                .default_value("allow")
+                .global(true),
+        )
+        .arg(
+            arg!(--includes "Allow @include in card files, read relative to the including file. Never for stdin")
+                .global(true),
+        )
+        .subcommand(input_args(Command::new("json").about("Convert the cards to json")))
+        .subcommand(
+            Command::new("diff")
+                .about("Compare the resolved cards of two files")
+                .arg(arg!(<old> "The original file"))
+                .arg(arg!(<new> "The changed file")),
+        )
+        .subcommand(
+            Command::new("merge")
+                .about("Three way merge of resolved cards, writing conflict markers. Loses @def, @const and @param lines")
+                .arg(arg!(<base> "The common ancestor"))
+                .arg(arg!(<ours> "Our version"))
+                .arg(arg!(<theirs> "Their version"))
+                .arg(arg!(-o --output <file> "Write the merged cards here instead of stdout")),
+        )
+        .subcommand(
+            Command::new("watch")
+                .about("Check the files each time they change, writing the converted cards when they parse")
+                .arg(arg!(<files> ... "The card files, read as one deck"))
+                .arg(arg!(-o --output <file> "Write the converted cards here instead of stdout"))
+                .arg(
+                    arg!(-f --format <format> "The output format")
+                        .value_parser(["json", "crd"])
+                        .default_value("json"),
+                ),
+        )
+        .subcommand(
+            Command::new("render")
+                .about("Lay out print and play sheets as svg, filling {{property.path}} placeholders in a card template")
+                .arg(
+                    Arg::new("files")
+                        .num_args(1..)
+                        .help("Card files or glob patterns, read as one deck (default stdin)"),
+                )
+                .arg(arg!(-t --template <file> "An svg for one card, including its bleed").required(true))
+                .arg(arg!(-o --output <prefix> "Sheets are written to <prefix>_1.svg, <prefix>_2.svg ...").default_value("sheet"))
+                .arg(arg!(-p --paper <paper> "The paper size").value_parser(["a4", "letter"]).default_value("a4"))
+                .arg(arg!(--card <size> "The card size in mm without bleed").default_value("63x88"))
+                .arg(arg!(--bleed <mm> "The bleed around each card").value_parser(clap::value_parser!(f64)).default_value("3"))
+                .arg(arg!(--margin <mm> "The minimum page margin").value_parser(clap::value_parser!(f64)).default_value("5"))
+                .arg(arg!(--"no-cut-marks" "Leave out the cut marks")),
+        )
+        .subcommand(
+            input_args(Command::new("html"))
+                .about("Build a searchable html page of every card")
+                .arg(arg!(-t --template <file> "An html page with {{title}} and {{cards}} placeholders"))
+                .arg(arg!(--title <title> "The page title").default_value("Cards")),
+        )
+        .subcommand(
+            input_args(Command::new("markdown"))
+                .about("Write markdown tables of the cards, one per @section or @def group")
+                .arg(arg!(-c --columns <columns> "Comma separated property names to show"))
+                .arg(arg!(-w --whole "Write one table for the whole deck")),
+        )
+        .subcommand(
+            input_args(Command::new("filter"))
+                .about("Write only the cards that match every condition, with their resolved data")
+                .arg(arg!(-w --where <condition> ... "eg: \"cost<=3\", \"type=spell\", \"jobs\" (has jobs) or \"!jobs\""))
+                .arg(arg!(-n --name <glob> "Only cards with names matching the glob, eg \"Arch*\""))
+                .arg(arg!(--"name-regex" <regex> "Only cards with names matching the regex"))
+                .arg(arg!(-t --tag <tag> ... "Only cards with all of these tags"))
+                .arg(
+                    arg!(-f --format <format> "The output format")
+                        .value_parser(["crd", "json", "markdown", "html"])
+                        .default_value("crd"),
+                ),
+        )
+        .subcommand(
+            input_args(Command::new("simulate"))
+                .about("The chance of drawing cards that match every condition, exactly and by shuffling")
+                .arg(arg!(-w --where <condition> ... "eg: \"cost<=2\", as for filter"))
+                .arg(arg!(--hand <n> "Cards in a hand").value_parser(clap::value_parser!(usize)).default_value("5"))
+                .arg(arg!(--"at-least" <n> "How many matching cards the hand needs").value_parser(clap::value_parser!(usize)).default_value("1"))
+                .arg(arg!(--trials <n> "How many hands to shuffle and draw").value_parser(clap::value_parser!(usize)).default_value("10000"))
+                .arg(arg!(--seed <n> "Seed for the shuffles").value_parser(clap::value_parser!(u64)).default_value("0"))
+                .arg(arg!(--show <n> "Print this many example hands").value_parser(clap::value_parser!(usize)).default_value("0")),
+        )
+        .subcommand(
+            input_args(Command::new("extract-strings"))
+                .about("Write the text of every card as a table for translators")
+                .arg(arg!(-l --lang <lang> "The language being translated to, to include existing translations").required(true))
+                .arg(
+                    arg!(-f --format <format> "The table format")
+                        .value_parser(["po", "csv"])
+                        .default_value("po"),
+                ),
+        )
+        .subcommand(
+            input_args(Command::new("apply-translations"))
+                .about("Add the translations from a table to the cards, as key@lang properties")
+                .arg(arg!(-l --lang <lang> "The language of the translations").required(true))
+                .arg(arg!(-t --table <file> "The .po or .csv table of translations").required(true)),
+        )
+        .subcommand(
+            Command::new("lint")
+                .about("Check card files for common mistakes")
+                .arg(
+                    Arg::new("files")
+                        .num_args(1..)
+                        .help("Card files or glob patterns, each checked alone (default stdin)"),
+                )
+                .arg(arg!(--only <rule> ... "Only check these rules"))
+                .arg(arg!(--disable <rule> ... "Do not check these rules"))
+                .arg(
+                    arg!(--distance <n> "How many edits apart property names are reported as similar")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("1"),
+                )
+                .after_help(
+                    "Rules: duplicate-name, unused-const, unfilled-param, redundant-default, similar-property, empty-list, broken-ref",
+                ),
+        )
+        .get_matches();
+
+    match matches.subcommand() {
+        Some(("diff", sub)) => {
+            let old = load_file(sub, "old")?;
+            let new = load_file(sub, "new")?;
+            cli::diff(&old, &new, &mut io::stdout().lock())?;
+        }
+        Some(("merge", sub)) => {
+            let base = load_file(sub, "base")?;
+            let ours = load_file(sub, "ours")?;
+            let theirs = load_file(sub, "theirs")?;
+            let conflicts = with_output(sub, |w| cli::merge(&base, &ours, &theirs, w))?;
+            for c in &conflicts {
+                eprintln!("CONFLICT {}", c);
+            }
+            if !conflicts.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Some(("watch", sub)) => {
+            let files = cli::expand_globs(sub.get_many::<String>("files").unwrap())?;
+            let output = sub.get_one::<String>("output").map(Path::new);
+            cli::watch(files, output, format(sub), &parser_options(sub), &mut io::stdout(), &mut io::stderr())?;
+        }
+        Some(("render", sub)) => {
+            let cards = load_input(sub)?;
+            let tpath = Path::new(sub.get_one::<String>("template").unwrap());
+            let template = std::fs::read_to_string(tpath).map_err(io_err(tpath))?;
+            let layout = Layout {
+                paper: match sub.get_one::<String>("paper").map(|s| s.as_str()) {
+                    Some("letter") => Layout::LETTER,
+                    _ => Layout::A4,
+                },
+                card: cli::parse_card_size(sub.get_one::<String>("card").unwrap())?,
+                bleed: *sub.get_one::<f64>("bleed").unwrap(),
+                margin: *sub.get_one::<f64>("margin").unwrap(),
+                cut_marks: !sub.get_flag("no-cut-marks"),
+            };
+            let prefix = sub.get_one::<String>("output").unwrap();
+            cli::render(&cards, &template, &layout, |n| {
+                let f = PathBuf::from(format!("{}_{}.svg", prefix, n));
+                std::fs::File::create(&f).map_err(io_err(&f))
+            })?;
+        }
+        Some(("html", sub)) => {
+            let cards = load_input(sub)?;
+            let template = match sub.get_one::<String>("template") {
+                Some(t) => std::fs::read_to_string(t).map_err(io_err(Path::new(t)))?,
+                None => html::DEFAULT_TEMPLATE.to_string(),
+            };
+            let title = sub.get_one::<String>("title").unwrap();
+            with_output(sub, |w| cli::html(&cards, title, &template, w))?;
+        }
+        Some(("markdown", sub)) => {
+            let sections = load_input_sections(sub)?;
+            let columns: Option<Vec<String>> = sub
+                .get_one::<String>("columns")
+                .map(|c| c.split(',').map(|s| s.trim().to_string()).collect());
+            with_output(sub, |w| cli::markdown(sections, sub.get_flag("whole"), columns.as_deref(), w))?;
+        }
+        Some(("lint", sub)) => {
+            let mut conf = LintConfig {
+                max_distance: *sub.get_one::<usize>("distance").unwrap(),
+                ..LintConfig::default()
+            };
+            let rules = |id: &str| -> CheckRes<Vec<Rule>> {
+                strings(sub, id)
+                    .iter()
+                    .map(|r| Rule::from_name(r).ok_or_else(|| CheckErr::Other(format!("Unknown lint rule '{}'", r))))
+                    .collect()
+            };
+            let only = rules("only")?;
+            if !only.is_empty() {
+                conf.rules = only.into_iter().collect();
+            }
+            for r in rules("disable")? {
+                conf.rules.remove(&r);
+            }
 
+            let mut sources = Vec::new();
+            match sub.get_many::<String>("files") {
+                Some(f) => {
+                    for f in cli::expand_globs(f)? {
+                        let s = std::fs::read_to_string(&f).map_err(io_err(&f))?;
+                        sources.push((f.display().to_string(), s));
+                    }
+                }
+                None => {
+                    let mut buf = String::new();
+                    io::Read::read_to_string(&mut io::stdin(), &mut buf)
+                        .map_err(|e| CheckErr::Io(format!("stdin: {}", e)))?;
+                    sources.push((String::new(), buf));
+                }
+            }
+            if cli::lint(&sources, &conf, &mut io::stdout().lock())? {
+                std::process::exit(1);
+            }
+        }
+        Some(("filter", sub)) => {
+            let cards = load_input(sub)?;
+            let opts = FilterOptions {
+                wheres: strings(sub, "where"),
+                name: sub.get_one::<String>("name").cloned(),
+                name_regex: sub.get_one::<String>("name-regex").cloned(),
+                tags: strings(sub, "tag"),
+                format: format(sub),
+            };
+            with_output(sub, |w| cli::filter(cards, &opts, w))?;
+        }
+        Some(("simulate", sub)) => {
+            let cards = load_input(sub)?;
+            let num = |id: &str| *sub.get_one::<usize>(id).unwrap();
+            let opts = SimOptions {
+                wheres: strings(sub, "where"),
+                hand: num("hand"),
+                at_least: num("at-least"),
+                trials: num("trials"),
+                seed: *sub.get_one::<u64>("seed").unwrap(),
+                show: num("show"),
+            };
+            cli::simulate(&cards, &opts, &mut io::stdout().lock())?;
+        }
+        Some(("extract-strings", sub)) => {
+            let cards = load_input(sub)?;
+            let lang = sub.get_one::<String>("lang").unwrap();
+            let tf = sub
+                .get_one::<String>("format")
+                .and_then(|f| TableFormat::from_name(f))
+                .unwrap_or_default();
+            with_output(sub, |w| cli::extract_strings(&cards, lang, tf, w))?;
+        }
+        Some(("apply-translations", sub)) => {
+            let cards = load_input(sub)?;
+            let lang = sub.get_one::<String>("lang").unwrap();
+            let tpath = Path::new(sub.get_one::<String>("table").unwrap());
+            let table = std::fs::read_to_string(tpath).map_err(io_err(tpath))?;
+            let tname = tpath.display().to_string();
+            let problems = with_output(sub, |w| {
+                cli::apply_translations(cards, lang, &table, TableFormat::from_path(tpath), w)
+                    .map_err(|e| match e {
+                        CheckErr::Parse(e) => CheckErr::Parse(e.in_file(&tname)),
+                        e => e,
+                    })
+            })?;
+            for p in problems {
+                eprintln!("Warning: {}", p);
+            }
+        }
+        Some(("json", sub)) => {
+            let cards = load_input(sub)?;
+            with_output(sub, |w| cli::write_cards(cards, Format::Json, w))?;
+        }
+        _ => {
+            let cards = load_input(&matches)?;
+            with_output(&matches, |w| cli::check(&cards, w))?;
+        }
+    }
+    Ok(())
+}
 
+pub fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
+    }
+}
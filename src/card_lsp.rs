@@ -0,0 +1,228 @@
+extern crate card_format;
+use card_format::outline::{char_col, utf16_col, Outline};
+use lsp_server::{Connection, Message, Notification, Request, Response};
+use lsp_types::notification::Notification as _;
+use lsp_types::request::Request as _;
+use lsp_types::*;
+use std::collections::HashMap;
+use std::error::Error;
+
+type Res<T> = Result<T, Box<dyn Error + Sync + Send>>;
+
+/// An open document, with its text kept to convert columns to and from utf16
+struct Doc {
+    text: String,
+    outline: Outline,
+}
+
+/// LSP counts columns in utf16 units from 0
+fn lsp_pos(s: &str, p: tokenate::Pos) -> Position {
+    Position::new(p.line.saturating_sub(1) as u32, utf16_col(s, p) as u32)
+}
+
+fn outline_at<'a>(
+    docs: &'a HashMap<Url, Doc>,
+    tdp: &TextDocumentPositionParams,
+) -> Option<(&'a Doc, usize, usize)> {
+    let d = docs.get(&tdp.text_document.uri)?;
+    let p = tdp.position;
+    let line = p.line as usize + 1;
+    Some((d, line, char_col(&d.text, line, p.character as usize)))
+}
+
+fn diagnostics(o: &Outline, s: &str) -> Vec<Diagnostic> {
+    let e = match &o.err {
+        Some(e) => e,
+        None => return Vec::new(),
+    };
+    let start = match e.pos() {
+        Some(p) => lsp_pos(s, p),
+        None => Position::new(s.split('\n').count().saturating_sub(1) as u32, 0),
+    };
+    vec![Diagnostic {
+        range: Range::new(start, Position::new(start.line, start.character + 1)),
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("card_format".to_string()),
+        message: e.to_string(),
+        ..Default::default()
+    }]
+}
+
+fn hover(o: &Outline, line: usize, col: usize) -> Option<Hover> {
+    let text = match o.var_ref_at(line, col) {
+        Some(v) => format!("${} = {}", v, o.consts.get(v)?.1),
+        None => o.resolved.get(o.card_at(line)?)?.to_string(),
+    };
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!("```\n{}\n```", text),
+        }),
+        range: None,
+    })
+}
+
+/// The start of the 1 based line, or the end of the document for a line past its end
+fn line_start(s: &str, line: usize) -> Position {
+    let last = s.split('\n').count() - 1;
+    match line.saturating_sub(1) {
+        l if l > last => {
+            let tail = s.rsplit('\n').next().unwrap_or("");
+            Position::new(last as u32, tail.encode_utf16().count() as u32)
+        }
+        l => Position::new(l as u32, 0),
+    }
+}
+
+#[allow(deprecated)]
+fn symbols(d: &Doc) -> Vec<DocumentSymbol> {
+    d.outline
+        .cards
+        .iter()
+        .map(|c| {
+            let range = Range::new(lsp_pos(&d.text, c.start), line_start(&d.text, c.end.line));
+            DocumentSymbol {
+                name: c.name.clone(),
+                detail: None,
+                kind: SymbolKind::OBJECT,
+                tags: None,
+                deprecated: None,
+                range,
+                selection_range: range,
+                children: None,
+            }
+        })
+        .collect()
+}
+
+fn handle_request(conn: &Connection, req: Request, docs: &HashMap<Url, Doc>) -> Res<()> {
+    let result = match req.method.as_str() {
+        request::HoverRequest::METHOD => {
+            let p: HoverParams = serde_json::from_value(req.params)?;
+            let h = outline_at(docs, &p.text_document_position_params)
+                .and_then(|(d, l, c)| hover(&d.outline, l, c));
+            serde_json::to_value(h)?
+        }
+        request::GotoDefinition::METHOD => {
+            let p: GotoDefinitionParams = serde_json::from_value(req.params)?;
+            let tdp = p.text_document_position_params;
+            let loc = outline_at(docs, &tdp).and_then(|(d, l, c)| {
+                let o = &d.outline;
+                let (pos, _) = o.consts.get(o.var_ref_at(l, c)?)?;
+                let pos = lsp_pos(&d.text, *pos);
+                Some(GotoDefinitionResponse::Scalar(Location::new(
+                    tdp.text_document.uri.clone(),
+                    Range::new(pos, pos),
+                )))
+            });
+            serde_json::to_value(loc)?
+        }
+        request::Completion::METHOD => {
+            let p: CompletionParams = serde_json::from_value(req.params)?;
+            let items: Vec<CompletionItem> = docs
+                .get(&p.text_document_position.text_document.uri)
+                .map(|d| {
+                    d.outline
+                        .props
+                        .iter()
+                        .map(|k| CompletionItem {
+                            label: k.clone(),
+                            kind: Some(CompletionItemKind::PROPERTY),
+                            ..Default::default()
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            serde_json::to_value(CompletionResponse::Array(items))?
+        }
+        request::DocumentSymbolRequest::METHOD => {
+            let p: DocumentSymbolParams = serde_json::from_value(req.params)?;
+            let syms = docs
+                .get(&p.text_document.uri)
+                .map(|o| DocumentSymbolResponse::Nested(symbols(o)));
+            serde_json::to_value(syms)?
+        }
+        _ => {
+            let resp = Response::new_err(
+                req.id,
+                lsp_server::ErrorCode::MethodNotFound as i32,
+                "Unhandled method".to_string(),
+            );
+            conn.sender.send(Message::Response(resp))?;
+            return Ok(());
+        }
+    };
+    conn.sender.send(Message::Response(Response {
+        id: req.id,
+        result: Some(result),
+        error: None,
+    }))?;
+    Ok(())
+}
+
+fn update(conn: &Connection, docs: &mut HashMap<Url, Doc>, uri: Url, s: String) -> Res<()> {
+    let outline = Outline::new(&s);
+    let params = PublishDiagnosticsParams {
+        uri: uri.clone(),
+        diagnostics: diagnostics(&outline, &s),
+        version: None,
+    };
+    docs.insert(uri, Doc { text: s, outline });
+    conn.sender.send(Message::Notification(Notification::new(
+        notification::PublishDiagnostics::METHOD.to_string(),
+        params,
+    )))?;
+    Ok(())
+}
+
+fn handle_notification(conn: &Connection, n: Notification, docs: &mut HashMap<Url, Doc>) -> Res<()> {
+    match n.method.as_str() {
+        notification::DidOpenTextDocument::METHOD => {
+            let p: DidOpenTextDocumentParams = serde_json::from_value(n.params)?;
+            update(conn, docs, p.text_document.uri, p.text_document.text)
+        }
+        notification::DidChangeTextDocument::METHOD => {
+            let p: DidChangeTextDocumentParams = serde_json::from_value(n.params)?;
+            match p.content_changes.into_iter().last() {
+                Some(c) => update(conn, docs, p.text_document.uri, c.text),
+                None => Ok(()),
+            }
+        }
+        notification::DidCloseTextDocument::METHOD => {
+            let p: DidCloseTextDocumentParams = serde_json::from_value(n.params)?;
+            docs.remove(&p.text_document.uri);
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn main() -> Res<()> {
+    let (conn, io_threads) = Connection::stdio();
+    let caps = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+    conn.initialize(serde_json::to_value(caps)?)?;
+
+    let mut docs = HashMap::new();
+    for msg in &conn.receiver {
+        match msg {
+            Message::Request(req) => {
+                if conn.handle_shutdown(&req)? {
+                    break;
+                }
+                handle_request(&conn, req, &docs)?;
+            }
+            Message::Notification(n) => handle_notification(&conn, n, &mut docs)?,
+            Message::Response(_) => {}
+        }
+    }
+    drop(conn);
+    io_threads.join()?;
+    Ok(())
+}
@@ -0,0 +1,583 @@
+//! The commands behind card_check, for embedding in other tools.
+//! Each takes cards already loaded and writes what it makes to any Write.
+use crate::card::{CData, Card};
+use crate::err::{AtErr, CardErr};
+use crate::filter::Predicate;
+use crate::lint::{self, Lint, LintConfig};
+use crate::merge::{self, Conflict};
+use crate::parse::{Parsed, Section};
+use crate::render::{render_sheets, Layout};
+use crate::simulate::{self, Simulator};
+use crate::{html, locale, markdown, ParserOptions};
+use notify::{RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Exit code when the cards could not be parsed
+pub const EXIT_PARSE: i32 = 65;
+/// Exit code when a file could not be read or written
+pub const EXIT_IO: i32 = 74;
+
+#[derive(Debug)]
+pub enum CheckErr {
+    Io(String),
+    Parse(AtErr),
+    Other(String),
+}
+
+impl CheckErr {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CheckErr::Parse(_) => EXIT_PARSE,
+            CheckErr::Io(_) => EXIT_IO,
+            CheckErr::Other(_) => 1,
+        }
+    }
+}
+
+impl std::fmt::Display for CheckErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CheckErr::Io(s) | CheckErr::Other(s) => write!(f, "{}", s),
+            CheckErr::Parse(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<AtErr> for CheckErr {
+    fn from(e: AtErr) -> Self {
+        CheckErr::Parse(e)
+    }
+}
+
+impl From<serde_json::Error> for CheckErr {
+    fn from(e: serde_json::Error) -> Self {
+        CheckErr::Other(e.to_string())
+    }
+}
+
+impl From<io::Error> for CheckErr {
+    fn from(e: io::Error) -> Self {
+        CheckErr::Io(e.to_string())
+    }
+}
+
+pub type CheckRes<T> = Result<T, CheckErr>;
+
+/// Name the file in an io error
+pub fn io_err<E: std::fmt::Display>(name: &Path) -> impl FnOnce(E) -> CheckErr + '_ {
+    move |e| CheckErr::Io(format!("{}: {}", name.display(), e))
+}
+
+/// How converted cards are written
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Format {
+    #[default]
+    Crd,
+    Json,
+    Markdown,
+    Html,
+}
+
+impl Format {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "crd" => Some(Format::Crd),
+            "json" => Some(Format::Json),
+            "markdown" => Some(Format::Markdown),
+            "html" => Some(Format::Html),
+            _ => None,
+        }
+    }
+}
+
+/// How a table of strings for translators is written
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum TableFormat {
+    #[default]
+    Po,
+    Csv,
+}
+
+impl TableFormat {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "po" => Some(TableFormat::Po),
+            "csv" => Some(TableFormat::Csv),
+            _ => None,
+        }
+    }
+
+    /// Csv for a ".csv" file, otherwise Po
+    pub fn from_path(p: &Path) -> Self {
+        match p.extension().and_then(|e| e.to_str()) {
+            Some("csv") => TableFormat::Csv,
+            _ => TableFormat::Po,
+        }
+    }
+}
+
+/// Expand any glob patterns, paths without glob characters are kept as they are
+/// so a missing file is reported as missing
+pub fn expand_globs<S: AsRef<str>, I: IntoIterator<Item = S>>(args: I) -> CheckRes<Vec<PathBuf>> {
+    let mut res = Vec::new();
+    for a in args {
+        let a = a.as_ref();
+        if !a.contains(['*', '?', '[']) {
+            res.push(PathBuf::from(a));
+            continue;
+        }
+        let paths = glob::glob(a).map_err(|e| CheckErr::Other(format!("{}: {}", a, e)))?;
+        let len = res.len();
+        for p in paths {
+            res.push(p.map_err(|e| CheckErr::Io(e.to_string()))?);
+        }
+        if res.len() == len {
+            return Err(CheckErr::Io(format!("{}: no files match", a)));
+        }
+    }
+    Ok(res)
+}
+
+/// Read the files as one deck with the options. Files are named in errors,
+/// and if opts.includes is set may include other files relative to themselves.
+/// Warnings are left in the result.
+pub fn load_parsed(files: &[PathBuf], opts: &ParserOptions) -> CheckRes<Parsed> {
+    let mut sources = Vec::new();
+    for f in files {
+        let s = std::fs::read_to_string(f).map_err(io_err(f))?;
+        sources.push((f.display().to_string(), s));
+    }
+    Ok(crate::parse_source_sections(&sources, opts)?)
+}
+
+/// Read a deck from r, such as stdin. It has no path to find includes from, so they are always off.
+pub fn read_parsed<R: Read>(r: &mut R, opts: &ParserOptions) -> CheckRes<Parsed> {
+    let mut buf = String::new();
+    r.read_to_string(&mut buf)?;
+    let mut p = opts.clone().includes(false).parser(&buf);
+    let mut res = Parsed::default();
+    p.parse_into(&mut res)?;
+    Ok(res)
+}
+
+pub fn write_warnings<W: Write + ?Sized>(p: &Parsed, w: &mut W) -> CheckRes<()> {
+    for e in &p.warnings {
+        writeln!(w, "Warning: {}", e)?;
+    }
+    Ok(())
+}
+
+/// Every card with its index, what card_check prints without a subcommand
+pub fn check<W: Write + ?Sized>(cards: &[Card], w: &mut W) -> CheckRes<()> {
+    for (i, c) in cards.iter().enumerate() {
+        writeln!(w, "{} = {}", i, c)?;
+    }
+    Ok(())
+}
+
+pub fn convert(cards: Vec<Card>, format: Format) -> CheckRes<String> {
+    Ok(match format {
+        Format::Json => {
+            let mp: Vec<CData> = cards.into_iter().map(Card::flatten).collect();
+            serde_json::to_string_pretty(&mp)?
+        }
+        Format::Markdown => markdown::markdown_table(&cards, &markdown::all_columns(&cards)),
+        Format::Html => html::html_page(&cards, "Cards", html::DEFAULT_TEMPLATE),
+        Format::Crd => cards.iter().map(|c| format!("{}\n", c)).collect(),
+    })
+}
+
+pub fn write_cards<W: Write + ?Sized>(cards: Vec<Card>, format: Format, w: &mut W) -> CheckRes<()> {
+    w.write_all(convert(cards, format)?.as_bytes())?;
+    Ok(())
+}
+
+pub fn diff<W: Write + ?Sized>(old: &[Card], new: &[Card], w: &mut W) -> CheckRes<()> {
+    for d in crate::diff::diff_cards(old, new) {
+        writeln!(w, "{}", d)?;
+    }
+    Ok(())
+}
+
+/// Write the merged cards, fully resolved, so any @def, @const and @param lines are not kept.
+/// A card with a conflict is written as both versions between git style conflict markers,
+/// so the output will not parse until it is resolved.
+pub fn merge<W: Write + ?Sized>(base: &[Card], ours: &[Card], theirs: &[Card], w: &mut W) -> CheckRes<Vec<Conflict>> {
+    let m = merge::merge_cards(base, ours, theirs);
+    let conflicted: BTreeSet<&str> = m.conflicts.iter().map(|c| c.name.as_str()).collect();
+    let side = |cards: &[Card], name: &str| cards.iter().find(|c| c.name == name).cloned();
+    for c in &m.cards {
+        if !conflicted.contains(c.name.as_str()) {
+            writeln!(w, "{}", c)?;
+            continue;
+        }
+        writeln!(w, "<<<<<<< ours")?;
+        if let Some(o) = side(ours, &c.name) {
+            write!(w, "{}", o)?;
+        }
+        writeln!(w, "=======")?;
+        if let Some(t) = side(theirs, &c.name) {
+            write!(w, "{}", t)?;
+        }
+        writeln!(w, ">>>>>>> theirs\n")?;
+    }
+    Ok(m.conflicts)
+}
+
+/// "63x88" as a card size in mm
+pub fn parse_card_size(s: &str) -> CheckRes<(f64, f64)> {
+    s.split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .ok_or_else(|| CheckErr::Other(format!("Card size '{}' should look like 63x88", s)))
+}
+
+/// Lay out print and play sheets, writing each page as svg to the writer page gives for its number, from 1.
+/// Returns how many pages there were.
+pub fn render<F, W>(cards: &[Card], template: &str, layout: &Layout, mut page: F) -> CheckRes<usize>
+where
+    F: FnMut(usize) -> CheckRes<W>,
+    W: Write,
+{
+    let sheets = render_sheets(cards, template, layout);
+    for (i, s) in sheets.iter().enumerate() {
+        page(i + 1)?.write_all(s.as_bytes())?;
+    }
+    Ok(sheets.len())
+}
+
+pub fn html<W: Write + ?Sized>(cards: &[Card], title: &str, template: &str, w: &mut W) -> CheckRes<()> {
+    w.write_all(html::html_page(cards, title, template).as_bytes())?;
+    Ok(())
+}
+
+/// A table per section, or one for the whole deck
+pub fn markdown<W: Write + ?Sized>(
+    mut sections: Vec<(Section, Vec<Card>)>,
+    whole: bool,
+    columns: Option<&[String]>,
+    w: &mut W,
+) -> CheckRes<()> {
+    if whole {
+        let cards = sections.into_iter().flat_map(|(_, v)| v).collect();
+        sections = vec![(Section::default(), cards)];
+    }
+    w.write_all(markdown::markdown_sections(&sections, columns).as_bytes())?;
+    Ok(())
+}
+
+/// Lint each named source alone, writing the problems found after the name.
+/// Returns true if there were any.
+pub fn lint<N: AsRef<str>, S: AsRef<str>, W: Write + ?Sized>(
+    sources: &[(N, S)],
+    conf: &LintConfig,
+    w: &mut W,
+) -> CheckRes<bool> {
+    let mut found = false;
+    for (name, s) in sources {
+        let name = name.as_ref();
+        let lints: Vec<Lint> = lint::lint(s.as_ref(), conf).map_err(|e| match name {
+            "" => e,
+            n => e.in_file(n),
+        })?;
+        for l in lints {
+            match name {
+                "" => writeln!(w, "{}", l)?,
+                n => writeln!(w, "{}:{}", n, l)?,
+            }
+            found = true;
+        }
+    }
+    Ok(found)
+}
+
+/// Read "--where" conditions
+pub fn parse_predicates<S: AsRef<str>>(wheres: &[S]) -> CheckRes<Vec<Predicate>> {
+    wheres
+        .iter()
+        .map(|w| Predicate::parse(w.as_ref()).map_err(|e: CardErr| CheckErr::Other(e.to_string())))
+        .collect()
+}
+
+/// Which cards filter keeps. They must match every condition given.
+#[derive(Clone, Debug, Default)]
+pub struct FilterOptions {
+    /// eg "cost<=3", "type=spell", "jobs" (has jobs) or "!jobs"
+    pub wheres: Vec<String>,
+    /// A glob for the names, eg "Arch*"
+    pub name: Option<String>,
+    pub name_regex: Option<String>,
+    pub tags: Vec<String>,
+    pub format: Format,
+}
+
+pub fn filter_cards(mut cards: Vec<Card>, opts: &FilterOptions) -> CheckRes<Vec<Card>> {
+    let mut preds = parse_predicates(&opts.wheres)?;
+    if let Some(n) = &opts.name {
+        preds.extend(parse_predicates(&[format!("name={}", n)])?);
+    }
+    preds.extend(opts.tags.iter().map(|t| Predicate::Tag(t.clone())));
+    cards.retain(|c| preds.iter().all(|p| p.matches(c)));
+    if let Some(re) = &opts.name_regex {
+        let re = regex::Regex::new(re).map_err(|e| CheckErr::Other(e.to_string()))?;
+        cards.retain(|c| re.is_match(&c.name));
+    }
+    Ok(cards)
+}
+
+pub fn filter<W: Write + ?Sized>(cards: Vec<Card>, opts: &FilterOptions, w: &mut W) -> CheckRes<()> {
+    write_cards(filter_cards(cards, opts)?, opts.format, w)
+}
+
+#[derive(Clone, Debug)]
+pub struct SimOptions {
+    /// The conditions a card must meet to count, as for filter
+    pub wheres: Vec<String>,
+    pub hand: usize,
+    /// How many matching cards the hand needs
+    pub at_least: usize,
+    pub trials: usize,
+    pub seed: u64,
+    /// How many example hands to print
+    pub show: usize,
+}
+
+impl Default for SimOptions {
+    fn default() -> Self {
+        SimOptions {
+            wheres: Vec::new(),
+            hand: 5,
+            at_least: 1,
+            trials: 10000,
+            seed: 0,
+            show: 0,
+        }
+    }
+}
+
+/// The chance of drawing enough matching cards, exactly and by shuffling
+pub fn simulate<W: Write + ?Sized>(cards: &[Card], opts: &SimOptions, w: &mut W) -> CheckRes<()> {
+    let preds = parse_predicates(&opts.wheres)?;
+    let pred = |c: &Card| preds.iter().all(|p| p.matches(c));
+    let mut sim = Simulator::new(cards, opts.seed);
+    for _ in 0..opts.show {
+        let names: Vec<&str> = sim.draw(opts.hand).iter().map(|c| c.name.as_str()).collect();
+        writeln!(w, "Hand: {}", names.join(", "))?;
+    }
+    writeln!(
+        w,
+        "Deck of {}, at least {} matching in {} cards",
+        sim.deck_size(),
+        opts.at_least,
+        opts.hand
+    )?;
+    writeln!(w, "Exact: {:.4}", simulate::exact(cards, opts.hand, opts.at_least, pred))?;
+    writeln!(
+        w,
+        "Monte Carlo ({} trials): {:.4}",
+        opts.trials,
+        sim.monte_carlo(opts.hand, opts.at_least, pred, opts.trials)
+    )?;
+    Ok(())
+}
+
+pub fn extract_strings<W: Write + ?Sized>(cards: &[Card], lang: &str, format: TableFormat, w: &mut W) -> CheckRes<()> {
+    let entries = locale::extract_strings(cards, lang);
+    let out = match format {
+        TableFormat::Csv => locale::to_csv(&entries),
+        TableFormat::Po => locale::to_po(&entries, lang),
+    };
+    w.write_all(out.as_bytes())?;
+    Ok(())
+}
+
+/// Add the translations in the table to the cards, and write them.
+/// Returns the entries that no longer match the cards.
+pub fn apply_translations<W: Write + ?Sized>(
+    mut cards: Vec<Card>,
+    lang: &str,
+    table: &str,
+    format: TableFormat,
+    w: &mut W,
+) -> CheckRes<Vec<String>> {
+    let entries = match format {
+        TableFormat::Csv => locale::from_csv(table),
+        TableFormat::Po => locale::from_po(table),
+    }?;
+    let problems = locale::apply_translations(&mut cards, lang, &entries);
+    write_cards(cards, Format::Crd, w)?;
+    Ok(problems)
+}
+
+/// Returns the files included by the deck, so they can be watched too
+fn rebuild(
+    files: &[PathBuf],
+    output: Option<&Path>,
+    format: Format,
+    opts: &ParserOptions,
+    out: &mut dyn Write,
+    status: &mut dyn Write,
+) -> CheckRes<Vec<PathBuf>> {
+    let mut included = Vec::new();
+    let res = load_parsed(files, opts).and_then(|p| {
+        write_warnings(&p, status)?;
+        included = p.included.iter().filter_map(|f| Path::new(f).canonicalize().ok()).collect();
+        convert(p.cards(), format)
+    });
+    match (res, output) {
+        (Ok(s), Some(o)) => match std::fs::write(o, s) {
+            Ok(_) => writeln!(status, "OK: wrote {}", o.display())?,
+            Err(e) => writeln!(status, "Error writing {}: {}", o.display(), e)?,
+        },
+        (Ok(s), None) => writeln!(out, "{}", s)?,
+        (Err(e), _) => writeln!(status, "Error: {}", e)?,
+    }
+    Ok(included)
+}
+
+/// Rebuild whenever one of the files, or a file they include, changes, writing to the output file or out.
+/// Errors in the cards and what was written go to status.
+/// The parent folders are watched, so editors that replace files on save are still seen.
+pub fn watch(
+    files: Vec<PathBuf>,
+    output: Option<&Path>,
+    format: Format,
+    opts: &ParserOptions,
+    out: &mut dyn Write,
+    status: &mut dyn Write,
+) -> CheckRes<()> {
+    let notify_err = |e: notify::Error| CheckErr::Other(e.to_string());
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).map_err(notify_err)?;
+    let files: Vec<PathBuf> = files
+        .iter()
+        .map(|f| f.canonicalize().map_err(io_err(f)))
+        .collect::<Result<_, _>>()?;
+    let mut watched: Vec<PathBuf> = Vec::new();
+    let mut dirs: BTreeSet<PathBuf> = BTreeSet::new();
+    loop {
+        watched.clear();
+        watched.extend(files.iter().cloned());
+        watched.extend(rebuild(&files, output, format, opts, out, status)?);
+        for f in &watched {
+            let dir = f.parent().unwrap_or(Path::new(".")).to_path_buf();
+            if !dirs.contains(&dir) {
+                watcher.watch(&dir, RecursiveMode::NonRecursive).map_err(notify_err)?;
+                dirs.insert(dir);
+            }
+        }
+        loop {
+            let ev = rx
+                .recv()
+                .map_err(|e| CheckErr::Other(e.to_string()))?
+                .map_err(notify_err)?;
+            if ev.kind.is_access() || !ev.paths.iter().any(|p| watched.contains(p)) {
+                continue;
+            }
+            //let the editor finish saving
+            while rx.recv_timeout(Duration::from_millis(100)).is_ok() {}
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_cards;
+    fn deck() -> Vec<Card> {
+        parse_cards("@param cost\nArcher 3 %core:\n.text:Shoot\nMage 1:\n").unwrap()
+    }
+    fn out<F: FnOnce(&mut Vec<u8>) -> CheckRes<T>, T>(f: F) -> String {
+        let mut w = Vec::new();
+        f(&mut w).unwrap();
+        String::from_utf8(w).unwrap()
+    }
+    #[test]
+    pub fn test_check_lists_cards() {
+        assert!(out(|w| check(&deck(), w)).starts_with("0 = 1*Archer %core : \n"));
+    }
+    #[test]
+    pub fn test_filter_by_where_and_tag() {
+        let opts = FilterOptions {
+            wheres: vec!["cost<=2".to_string()],
+            format: Format::Json,
+            ..FilterOptions::default()
+        };
+        let json = out(|w| filter(deck(), &opts, w));
+        assert!(json.contains("\"name\": \"Mage\"") && !json.contains("Archer"));
+        let tagged = FilterOptions {
+            tags: vec!["core".to_string()],
+            ..FilterOptions::default()
+        };
+        assert_eq!(filter_cards(deck(), &tagged).unwrap().len(), 1);
+        assert!(parse_predicates(&["<3"]).is_err());
+    }
+    #[test]
+    pub fn test_diff_writes_changes() {
+        let mut changed = deck();
+        changed[1].data.insert("cost".to_string(), CData::N(2));
+        assert_eq!(out(|w| diff(&deck(), &changed, w)).lines().count(), 1);
+    }
+    #[test]
+    pub fn test_lint_names_the_file() {
+        let mut found = false;
+        let s = out(|w| {
+            found = lint(&[("a.crd", "@const x:1\nA:\n")], &LintConfig::default(), w)?;
+            Ok(())
+        });
+        assert!(found && s.starts_with("a.crd:"));
+    }
+    #[test]
+    pub fn test_read_parsed_errors() {
+        let parsed = read_parsed(&mut "A:\n.x:$nope".as_bytes(), &ParserOptions::new());
+        assert!(matches!(parsed, Err(CheckErr::Parse(_))));
+        let piped = read_parsed(&mut "@include \"Cargo.toml\"".as_bytes(), &ParserOptions::new().includes(true));
+        assert!(matches!(piped, Err(CheckErr::Parse(_))));
+        assert_eq!(CheckErr::Io(String::new()).exit_code(), EXIT_IO);
+    }
+    #[test]
+    pub fn test_render_writes_each_page() {
+        let cards = parse_cards("7*Archer:").unwrap();
+        let tp = "<svg width=\"69mm\" height=\"94mm\"><text>{{name}}</text></svg>";
+        let mut pages = Vec::new();
+        let n = render(&cards, tp, &Layout::default(), |n| {
+            pages.push(n);
+            Ok(Vec::new())
+        })
+        .unwrap();
+        assert_eq!((n, pages), (2, vec![1, 2]));
+    }
+    #[test]
+    pub fn test_rebuild_writes_output_and_status() {
+        let f = std::env::temp_dir().join(format!("card_check_rebuild_{}.crd", std::process::id()));
+        let files = vec![f.clone()];
+        std::fs::write(&f, "Archer:\n.cost:3").unwrap();
+        let (mut w, mut status) = (Vec::new(), Vec::new());
+        rebuild(&files, None, Format::Crd, &ParserOptions::new(), &mut w, &mut status).unwrap();
+        assert!(String::from_utf8(w).unwrap().starts_with("1*Archer : \n.cost:3"));
+        std::fs::write(&f, "Archer:\n.cost:$nope").unwrap();
+        let (mut w, mut status) = (Vec::new(), Vec::new());
+        rebuild(&files, None, Format::Crd, &ParserOptions::new(), &mut w, &mut status).unwrap();
+        std::fs::remove_file(&f).unwrap();
+        assert!(w.is_empty());
+        assert!(String::from_utf8(status).unwrap().starts_with("Error: "));
+    }
+    #[test]
+    pub fn test_merge_marks_conflicts() {
+        let base = parse_cards("\"Big Dog\":\n.cost:3\nMage:\n.cost:5").unwrap();
+        let ours = parse_cards("\"Big Dog\":\n.cost:2\nMage:\n.cost:4").unwrap();
+        let theirs = parse_cards("\"Big Dog\":\n.cost:3\nMage:\n.cost:6").unwrap();
+        let mut w = Vec::new();
+        let conflicts = merge(&base, &ours, &theirs, &mut w).unwrap();
+        assert_eq!(conflicts.len(), 1);
+        let s = String::from_utf8(w).unwrap();
+        assert!(s.starts_with("1*\"Big Dog\" : \n.cost:2\n"));
+        assert!(s.contains("<<<<<<< ours\n1*Mage : \n.cost:4\n=======\n1*Mage : \n.cost:6\n>>>>>>> theirs\n"));
+        assert!(parse_cards(&s).is_err());
+        assert_eq!(parse_cards(s.split("<<<").next().unwrap()).unwrap()[0].name, "Big Dog");
+    }
+}
@@ -0,0 +1,119 @@
+use serde_derive::*;
+use std::fmt::{self, Display};
+
+/// A dice roll such as "2d6+1": count dice with sides faces, added together with the modifier
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Dice {
+    pub count: u32,
+    pub sides: u32,
+    pub modifier: isize,
+}
+
+impl Dice {
+    pub fn new(count: u32, sides: u32, modifier: isize) -> Self {
+        Dice {
+            count,
+            sides,
+            modifier,
+        }
+    }
+
+    /// Read "2d6", "1d20+3" or "3d8-2". The count is needed, so "d6" is not dice.
+    pub fn parse(s: &str) -> Option<Dice> {
+        let (count, rest) = s.split_once(['d', 'D'])?;
+        let (sides, modifier) = match rest.find(['+', '-']) {
+            Some(n) => (&rest[..n], &rest[n..]),
+            None => (rest, ""),
+        };
+        let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+        if !digits(count) || !digits(sides) || !(modifier.is_empty() || digits(&modifier[1..])) {
+            return None;
+        }
+        let modifier = match modifier {
+            "" => 0,
+            m => m.parse().ok()?,
+        };
+        match (count.parse().ok()?, sides.parse().ok()?) {
+            (0, _) | (_, 0) => None,
+            (count, sides) => Some(Dice::new(count, sides, modifier)),
+        }
+    }
+
+    /// The lowest total, every die showing 1
+    pub fn min(&self) -> i64 {
+        (self.count as i64).saturating_add(self.modifier as i64)
+    }
+
+    /// The highest total, every die showing its top face
+    pub fn max(&self) -> i64 {
+        (self.count as i64 * self.sides as i64).saturating_add(self.modifier as i64)
+    }
+
+    /// The average total
+    pub fn mean(&self) -> f64 {
+        self.count as f64 * (self.sides as f64 + 1.) / 2. + self.modifier as f64
+    }
+
+    #[cfg(feature = "sim")]
+    pub fn roll<R: rand::Rng>(&self, rng: &mut R) -> i64 {
+        let dice: i64 = (0..self.count).map(|_| rng.gen_range(1..=self.sides) as i64).sum();
+        dice.saturating_add(self.modifier as i64)
+    }
+
+    /// Endless rolls, the same seed always giving the same totals
+    #[cfg(feature = "sim")]
+    pub fn rolls(self, seed: u64) -> impl Iterator<Item = i64> {
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        std::iter::repeat_with(move || self.roll(&mut rng))
+    }
+}
+
+impl Display for Dice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}d{}", self.count, self.sides)?;
+        match self.modifier {
+            0 => Ok(()),
+            m if m > 0 => write!(f, "+{}", m),
+            m => write!(f, "{}", m),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::card::CData;
+    use crate::filter::Predicate;
+    use crate::parse_cards;
+    #[test]
+    pub fn test_dice_values() {
+        let cards = parse_cards("Orc:\n.damage:2d6+1\n.hit:1d20\n.save:[3D8-2,d6]").unwrap();
+        let d = Dice::new(2, 6, 1);
+        assert_eq!(cards[0].data.get("damage"), Some(&CData::D(d)));
+        assert_eq!((d.min(), d.max(), d.mean()), (3, 13, 8.));
+        assert_eq!(Dice::parse("3D8-2"), Some(Dice::new(3, 8, -2)));
+        for bad in ["d6", "0d6", "2d0", "2d", "2d6+", "2d6x", "2d6+-1"] {
+            assert_eq!(Dice::parse(bad), None, "{}", bad);
+        }
+        let save = cards[0].data.get("save").unwrap().as_list().unwrap();
+        assert_eq!(save[1], CData::S("d6".to_string()));
+        assert_eq!(cards[0].to_string(), "1*Orc : \n.damage:2d6+1\n.hit:1d20\n.save:[3d8-2,\"d6\"]\n");
+        let json = serde_json::to_string(&CData::D(d)).unwrap();
+        assert_eq!(json, r#"{"count":2,"sides":6,"modifier":1}"#);
+        let matches = |p: &str| Predicate::parse(p).unwrap().matches(&cards[0]);
+        assert!(matches("damage>=8") && !matches("damage>8") && matches("damage=2d6*"));
+        assert!(parse_cards("A:\n.x:2d6+").is_err());
+        assert!(parse_cards("A:\n.x:-2d6").is_err());
+    }
+    #[cfg(feature = "sim")]
+    #[test]
+    pub fn test_dice_rolls() {
+        let d = Dice::new(3, 6, -1);
+        let rolls: Vec<i64> = d.rolls(7).take(1000).collect();
+        assert_eq!(rolls, d.rolls(7).take(1000).collect::<Vec<_>>());
+        assert!(rolls.iter().all(|r| (d.min()..=d.max()).contains(r)));
+        let mean = rolls.iter().sum::<i64>() as f64 / 1000.;
+        assert!((mean - d.mean()).abs() < 0.5);
+    }
+}
@@ -0,0 +1,240 @@
+use crate::card::{CData, Card};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathKey {
+    Key(String),
+    Index(usize),
+}
+
+impl Display for PathKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathKey::Key(k) => write!(f, ".{}", k),
+            PathKey::Index(i) => write!(f, "[{}]", i),
+        }
+    }
+}
+
+/// A single semantic difference between two resolved decks
+#[derive(Clone, Debug, PartialEq)]
+pub enum CardDiff {
+    Added(String),
+    Removed(String),
+    Renamed {
+        from: String,
+        to: String,
+    },
+    Num {
+        name: String,
+        old: usize,
+        new: usize,
+    },
+    Prop {
+        name: String,
+        path: Vec<PathKey>,
+        old: Option<CData>,
+        new: Option<CData>,
+    },
+    Tags {
+        name: String,
+        added: Vec<String>,
+        removed: Vec<String>,
+    },
+}
+
+impl Display for CardDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CardDiff::Added(n) => write!(f, "+ {}", n),
+            CardDiff::Removed(n) => write!(f, "- {}", n),
+            CardDiff::Renamed { from, to } => write!(f, "~ {} -> {}", from, to),
+            CardDiff::Num { name, old, new } => write!(f, "  {} num: {} -> {}", name, old, new),
+            CardDiff::Prop {
+                name,
+                path,
+                old,
+                new,
+            } => {
+                write!(f, "  {}", name)?;
+                for p in path {
+                    write!(f, "{}", p)?;
+                }
+                match (old, new) {
+                    (Some(o), Some(n)) => write!(f, ": {} -> {}", o, n),
+                    (Some(o), None) => write!(f, ": removed {}", o),
+                    (None, Some(n)) => write!(f, ": added {}", n),
+                    (None, None) => Ok(()),
+                }
+            }
+            CardDiff::Tags {
+                name,
+                added,
+                removed,
+            } => {
+                write!(f, "  {} tags:", name)?;
+                for t in added {
+                    write!(f, " +%{}", t)?;
+                }
+                for t in removed {
+                    write!(f, " -%{}", t)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Compare two decks by card name.
+/// Cards that disappear and reappear under a new name with identical contents count as renamed.
+pub fn diff_cards(old: &[Card], new: &[Card]) -> Vec<CardDiff> {
+    let new_map: BTreeMap<&str, &Card> = new.iter().rev().map(|c| (c.name.as_str(), c)).collect();
+    let old_map: BTreeMap<&str, &Card> = old.iter().rev().map(|c| (c.name.as_str(), c)).collect();
+    let mut added: Vec<&Card> = new
+        .iter()
+        .filter(|c| !old_map.contains_key(c.name.as_str()))
+        .collect();
+
+    let mut res = Vec::new();
+    for oc in old {
+        if !std::ptr::eq(old_map[oc.name.as_str()], oc) {
+            continue;
+        }
+        match new_map.get(oc.name.as_str()) {
+            Some(nc) => diff_card(oc, nc, &mut res),
+            None => match added
+                .iter()
+                .position(|nc| nc.num == oc.num && nc.data == oc.data)
+            {
+                Some(p) => {
+                    let nc = added.remove(p);
+                    res.push(CardDiff::Renamed {
+                        from: oc.name.clone(),
+                        to: nc.name.clone(),
+                    });
+                }
+                None => res.push(CardDiff::Removed(oc.name.clone())),
+            },
+        }
+    }
+    for nc in added {
+        if std::ptr::eq(new_map[nc.name.as_str()], nc) {
+            res.push(CardDiff::Added(nc.name.clone()));
+        }
+    }
+    res
+}
+
+pub fn diff_card(old: &Card, new: &Card, res: &mut Vec<CardDiff>) {
+    if old.num != new.num {
+        res.push(CardDiff::Num {
+            name: new.name.clone(),
+            old: old.num,
+            new: new.num,
+        });
+    }
+    if old.tags != new.tags {
+        res.push(CardDiff::Tags {
+            name: new.name.clone(),
+            added: new.tags.difference(&old.tags).cloned().collect(),
+            removed: old.tags.difference(&new.tags).cloned().collect(),
+        });
+    }
+    diff_map(&new.name, &mut Vec::new(), &old.data, &new.data, res);
+}
+
+fn diff_map(
+    name: &str,
+    path: &mut Vec<PathKey>,
+    old: &BTreeMap<String, CData>,
+    new: &BTreeMap<String, CData>,
+    res: &mut Vec<CardDiff>,
+) {
+    let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    for k in keys {
+        path.push(PathKey::Key(k.clone()));
+        diff_data(name, path, old.get(k), new.get(k), res);
+        path.pop();
+    }
+}
+
+fn diff_data(
+    name: &str,
+    path: &mut Vec<PathKey>,
+    old: Option<&CData>,
+    new: Option<&CData>,
+    res: &mut Vec<CardDiff>,
+) {
+    match (old, new) {
+        (Some(o), Some(n)) if o == n => {}
+        (Some(CData::M(o)), Some(CData::M(n))) => diff_map(name, path, o, n, res),
+        (Some(CData::L(o)), Some(CData::L(n))) => {
+            for i in 0..o.len().max(n.len()) {
+                path.push(PathKey::Index(i));
+                diff_data(name, path, o.get(i), n.get(i), res);
+                path.pop();
+            }
+        }
+        (o, n) => res.push(CardDiff::Prop {
+            name: name.to_string(),
+            path: path.clone(),
+            old: o.cloned(),
+            new: n.cloned(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_cards;
+    #[test]
+    pub fn test_diff_sees_defaults_and_renames() {
+        let old = parse_cards(
+            "@def\n.health:3\nDave:\n.size:4\nAlan:\n.pets:[cat,dog]\nBob:\n.size:1",
+        )
+        .unwrap();
+        let new = parse_cards(
+            "@def\n.health:4\n2*Dave:\n.size:4\nAlan:\n.pets:[cat,cow]\nRobert:\n.size:1\n.health:3",
+        )
+        .unwrap();
+        let d = diff_cards(&old, &new);
+        assert_eq!(
+            d[0],
+            CardDiff::Num {
+                name: "Dave".to_string(),
+                old: 1,
+                new: 2
+            }
+        );
+        assert_eq!(
+            d[1],
+            CardDiff::Prop {
+                name: "Dave".to_string(),
+                path: vec![PathKey::Key("health".to_string())],
+                old: Some(CData::N(3)),
+                new: Some(CData::N(4)),
+            }
+        );
+        assert_eq!(
+            d[3],
+            CardDiff::Prop {
+                name: "Alan".to_string(),
+                path: vec![PathKey::Key("pets".to_string()), PathKey::Index(1)],
+                old: Some(CData::S("dog".to_string())),
+                new: Some(CData::S("cow".to_string())),
+            }
+        );
+        assert_eq!(
+            d[4],
+            CardDiff::Renamed {
+                from: "Bob".to_string(),
+                to: "Robert".to_string()
+            }
+        );
+        assert_eq!(d.len(), 5);
+    }
+}
@@ -1,12 +1,12 @@
-use crate::tokenize::CardToken;
+use crate::tokenize::{token_text, CardToken};
 use thiserror::*;
 use tokenate::{Pos, TErr, Token};
 
 pub type CardRes<T> = Result<T, AtErr>;
 #[derive(Clone, Debug)]
 pub struct GotToken {
-    pos: Pos,
-    v: CardToken,
+    pub pos: Pos,
+    pub v: CardToken,
 }
 
 pub fn expected<T>(exp: &'static str, tk: &Token<CardToken>) -> Result<T, AtErr> {
@@ -19,6 +19,13 @@ pub enum CardErr {
     S(&'static str),
     #[error("Expected {}",.0)]
     Expected(&'static str),
+    /// The name, and where it was first defined
+    #[error("Card '{}' is already defined at {}",.0,.1)]
+    Duplicate(String, String),
+    #[error("Could not include '{}': {}",.0,.1)]
+    Include(String, String),
+    #[error("Could not read filter '{}'",.0)]
+    Filter(String),
     #[error("Cannot set Property")]
     Unset,
     #[error("{}",.0)]
@@ -29,14 +36,13 @@ impl CardErr {
     pub fn at(self, pos: Pos) -> AtErr {
         AtErr::At(self, pos)
     }
+    /// A borrowing tokenizer leaves Text empty, so the text is taken from the source
     pub fn got(self, t: &Token<CardToken>) -> AtErr {
-        AtErr::Got(
-            self,
-            GotToken {
-                pos: t.start,
-                v: t.value.clone(),
-            },
-        )
+        let v = match &t.value {
+            CardToken::Text(_) => CardToken::Text(token_text(t).into_owned()),
+            v => v.clone(),
+        };
+        AtErr::Got(self, Box::new(GotToken { pos: t.start, v }))
     }
     pub fn eof(self) -> AtErr {
         AtErr::EOF(self)
@@ -45,17 +51,48 @@ impl CardErr {
 
 #[derive(Debug, Error)]
 pub enum AtErr {
-    #[error("{} at {:?}" ,.0,.1)]
+    #[error("{} at {}" ,.0,line_col(*.1))]
     At(CardErr, Pos),
     #[error("{}, got EOF",.0)]
     EOF(CardErr),
     #[error("{}, got {:?}",.0,.1)]
-    Got(CardErr, GotToken),
+    Got(CardErr, Box<GotToken>),
     #[error("{}",.0)]
     TokenError(TErr),
 
     #[error("File Error")]
     FileErr,
+    #[error("{}",in_file(.0,.1))]
+    InFile(String, Box<AtErr>),
+}
+
+/// "line:col" of a position. The start of a source is at col 0, so it is shown as col 1 like the rest of its line.
+pub fn line_col(p: Pos) -> String {
+    format!("{}:{}", p.line, p.col.max(1))
+}
+
+/// "file:line:col: message" when the error has a plain position, otherwise "file: message"
+fn in_file(name: &str, e: &AtErr) -> String {
+    match e {
+        AtErr::At(e, p) => format!("{}:{}: {}", name, line_col(*p), e),
+        e => format!("{}: {}", name, e),
+    }
+}
+
+impl AtErr {
+    pub fn in_file(self, name: &str) -> AtErr {
+        AtErr::InFile(name.to_string(), Box::new(self))
+    }
+
+    pub fn pos(&self) -> Option<Pos> {
+        match self {
+            AtErr::At(_, p) => Some(*p),
+            AtErr::Got(_, g) => Some(g.pos),
+            AtErr::TokenError(e) => Some(e.pos),
+            AtErr::InFile(_, e) => e.pos(),
+            _ => None,
+        }
+    }
 }
 
 impl From<tokenate::TErr> for AtErr {
@@ -0,0 +1,234 @@
+use crate::card::{CData, Card};
+use crate::err::CardErr;
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+
+/// The cards carrying the tag, eg every card in the "%core" set
+pub fn cards_with_tag<'a>(cards: &'a [Card], tag: &str) -> Vec<&'a Card> {
+    cards.iter().filter(|c| c.has_tag(tag)).collect()
+}
+
+/// Every tag used by any of the cards, in order
+pub fn all_tags(cards: &[Card]) -> Vec<String> {
+    let mut res: Vec<String> = cards.iter().flat_map(|c| c.tags.iter().cloned()).collect();
+    res.sort();
+    res.dedup();
+    res
+}
+
+/// Match "*" to any run of characters and "?" to any one
+pub fn glob_match(pattern: &str, s: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+    // the last star seen, and where in s it was matched up to
+    let (mut pi, mut si, mut star) = (0, 0, None);
+    while si < s.len() {
+        match p.get(pi) {
+            Some('*') => {
+                star = Some((pi, si));
+                pi += 1;
+            }
+            Some(c) if *c == '?' || *c == s[si] => {
+                pi += 1;
+                si += 1;
+            }
+            _ => match star {
+                Some((sp, ss)) => {
+                    pi = sp + 1;
+                    si = ss + 1;
+                    star = Some((sp, ss + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+impl Op {
+    /// Longest first so "<=" is not read as "<"
+    const ALL: [(&'static str, Op); 6] = [
+        ("!=", Op::NotEq),
+        ("<=", Op::LtEq),
+        (">=", Op::GtEq),
+        ("=", Op::Eq),
+        ("<", Op::Lt),
+        (">", Op::Gt),
+    ];
+
+    pub fn symbol(&self) -> &'static str {
+        Op::ALL.iter().find(|(_, o)| o == self).map(|(s, _)| *s).unwrap_or("=")
+    }
+
+    fn test(&self, o: Ordering) -> bool {
+        match self {
+            Op::Eq => o == Ordering::Equal,
+            Op::NotEq => o != Ordering::Equal,
+            Op::Lt => o == Ordering::Less,
+            Op::LtEq => o != Ordering::Greater,
+            Op::Gt => o == Ordering::Greater,
+            Op::GtEq => o != Ordering::Less,
+        }
+    }
+}
+
+/// A condition on a card, read from text such as "cost<=3", "type=spell", "name=Arch*" or "!jobs"
+#[derive(Clone, Debug, PartialEq)]
+pub enum Predicate {
+    /// Compare the value at a dotted path, as used by Card::lookup
+    Cmp { path: String, op: Op, value: String },
+    /// The card has a value at the path
+    Has(String),
+    Tag(String),
+    Not(Box<Predicate>),
+}
+
+impl Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Predicate::Cmp { path, op, value } => write!(f, "{}{}{}", path, op.symbol(), value),
+            Predicate::Has(p) => write!(f, "{}", p),
+            Predicate::Tag(t) => write!(f, "%{}", t),
+            Predicate::Not(p) => write!(f, "!{}", p),
+        }
+    }
+}
+
+/// Compare one value, numbers as numbers, strings by glob for = and !=, lists if any item matches.
+/// Dice are ordered by their mean, so "damage>=7" finds 2d6 and up.
+/// A range equals any number it contains, so "play=4" finds 3..5,
+/// and is less or greater than a number only if all it contains is, so "play<6" finds 3..5 but not 3..
+fn cmp_data(d: &CData, op: Op, value: &str) -> bool {
+    match d {
+        CData::D(dice) => match (op, value.parse::<f64>()) {
+            (Op::Eq | Op::NotEq, _) | (_, Err(_)) => cmp_data(&CData::S(dice.to_string()), op, value),
+            (_, Ok(v)) => op.test(dice.mean().total_cmp(&v)),
+        },
+        CData::I(i) => match (op, value.parse::<i128>()) {
+            (Op::Eq, Ok(v)) => i.contains(v),
+            (Op::NotEq, Ok(v)) => !i.contains(v),
+            (Op::Lt | Op::LtEq, Ok(v)) => i.end.is_some_and(|e| op.test(e.cmp(&v))),
+            (Op::Gt | Op::GtEq, Ok(v)) => i.start.is_some_and(|s| op.test(s.cmp(&v))),
+            (Op::Eq | Op::NotEq, Err(_)) => cmp_data(&CData::S(i.to_string()), op, value),
+            (_, Err(_)) => false,
+        },
+        CData::N(n) => match value.parse::<i128>() {
+            Ok(v) => op.test(n.cmp(&v)),
+            Err(_) => op.test(n.to_string().as_str().cmp(value)),
+        },
+        CData::S(s) | CData::R(s) => match op {
+            Op::Eq => glob_match(value, s),
+            Op::NotEq => !glob_match(value, s),
+            _ => op.test(s.as_str().cmp(value)),
+        },
+        CData::L(l) if op == Op::NotEq => l.iter().all(|v| cmp_data(v, op, value)),
+        CData::L(l) => l.iter().any(|v| cmp_data(v, op, value)),
+        CData::M(_) => false,
+    }
+}
+
+impl Predicate {
+    pub fn parse(s: &str) -> Result<Predicate, CardErr> {
+        let s = s.trim();
+        if let Some(rest) = s.strip_prefix('!') {
+            return Ok(Predicate::Not(Box::new(Predicate::parse(rest)?)));
+        }
+        if let Some(t) = s.strip_prefix('%') {
+            return Ok(Predicate::Tag(t.to_string()));
+        }
+        let found = s
+            .char_indices()
+            .find_map(|(i, _)| Op::ALL.iter().find(|(sym, _)| s[i..].starts_with(sym)).map(|o| (i, o)));
+        let res = match found {
+            Some((i, (sym, op))) => Predicate::Cmp {
+                path: s[..i].trim().to_string(),
+                op: *op,
+                value: s[i + sym.len()..].trim().to_string(),
+            },
+            None => Predicate::Has(s.to_string()),
+        };
+        match res {
+            Predicate::Cmp { ref path, .. } | Predicate::Has(ref path) if path.is_empty() => {
+                Err(CardErr::Filter(s.to_string()))
+            }
+            r => Ok(r),
+        }
+    }
+
+    pub fn matches(&self, c: &Card) -> bool {
+        match self {
+            Predicate::Cmp { path, op, value } => match c.lookup(path) {
+                Some(d) => cmp_data(&d, *op, value),
+                None => *op == Op::NotEq,
+            },
+            Predicate::Has(path) => c.lookup(path).is_some(),
+            Predicate::Tag(t) => c.has_tag(t),
+            Predicate::Not(p) => !p.matches(c),
+        }
+    }
+}
+
+/// The cards matching every predicate
+pub fn filter_cards<'a>(cards: &'a [Card], preds: &[Predicate]) -> Vec<&'a Card> {
+    cards.iter().filter(|c| preds.iter().all(|p| p.matches(c))).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_cards;
+    #[test]
+    pub fn test_tags_in_headers() {
+        let s = "@param cost\n2*Archer %ranged 3 %core:\nMage %core:\nGiant 5:\n";
+        let cards = parse_cards(s).unwrap();
+        assert_eq!(cards.len(), 3);
+        assert!(cards[0].has_tag("ranged") && cards[0].has_tag("core"));
+        let core: Vec<&str> = cards_with_tag(&cards, "core").iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(core, vec!["Archer", "Mage"]);
+        assert_eq!(all_tags(&cards), vec!["core", "ranged"]);
+        assert_eq!(cards[0].to_string(), "2*Archer %core %ranged : \n.cost:3\n");
+        let again = parse_cards(&cards[0].to_string()).unwrap();
+        assert_eq!(again[0].tags, cards[0].tags);
+        // "%" and "&" inside a word are kept, they only start a tag or reference on their own
+        let cards = parse_cards("@param who\nTom&Jerry:\n@param bonus\nBoost 50%:\n").unwrap();
+        assert_eq!((cards[0].name.as_str(), cards[0].data.len()), ("Tom&Jerry", 0));
+        assert_eq!(cards[1].name, "Boost");
+        assert_eq!(cards[1].data.get("bonus"), Some(&CData::S("50%".to_string())));
+        assert!(cards[1].tags.is_empty());
+        let cards = parse_cards("@param a b\nA 1 %t 2:").unwrap();
+        assert_eq!(cards[0].data.get("b"), Some(&CData::N(2)));
+        assert!(cards[0].has_tag("t"));
+    }
+    #[test]
+    pub fn test_predicates() {
+        let s = "@param cost type
+2*Archer 3 troop:
+.jobs:[wood,stone]
+Mage 1 spell:
+\"Fire Ball\" 4 spell:";
+        let cards = parse_cards(s).unwrap();
+        let names = |preds: &[&str]| -> Vec<String> {
+            let preds: Vec<Predicate> = preds.iter().map(|p| Predicate::parse(p).unwrap()).collect();
+            filter_cards(&cards, &preds).iter().map(|c| c.name.clone()).collect()
+        };
+        assert_eq!(names(&["cost<=3"]), vec!["Archer", "Mage"]);
+        assert_eq!(names(&["type=spell", "cost>1"]), vec!["Fire Ball"]);
+        assert_eq!(names(&["jobs"]), vec!["Archer"]);
+        assert_eq!(names(&["!jobs", "name=*a*"]), vec!["Mage", "Fire Ball"]);
+        assert_eq!(names(&["jobs=st*", "num>=2"]), vec!["Archer"]);
+        assert_eq!(names(&["type!=spell"]), vec!["Archer"]);
+        assert!(Predicate::parse("<=3").is_err());
+        assert_eq!(Predicate::parse("!cost <= 3").unwrap().to_string(), "!cost<=3");
+        assert!(glob_match("F*B?ll", "Fire Ball") && !glob_match("F*x", "Fire Ball"));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::card::{CData, Card};
+use crate::render::xml_escape;
+use std::collections::BTreeMap;
+
+/// The page used when no template is given.
+/// "{{title}}" and "{{cards}}" are replaced with the title and the card list.
+pub const DEFAULT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{{title}}</title>
+<style>
+body { font-family: sans-serif; margin: 1em 2em; }
+#search { font-size: 1.2em; width: 20em; margin-bottom: 1em; }
+.card { border: 1px solid #888; border-radius: 6px; padding: 0.5em 1em; margin: 0.5em 0; }
+.card h2 { margin: 0.2em 0; }
+.card h2 a { color: inherit; text-decoration: none; }
+.num { color: #666; font-size: 0.8em; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 2px 6px; text-align: left; vertical-align: top; }
+</style>
+</head>
+<body>
+<h1>{{title}}</h1>
+<input id="search" type="search" placeholder="Search cards" oninput="search(this.value)">
+<div id="cards">
+{{cards}}
+</div>
+<script>
+function search(q) {
+    q = q.toLowerCase();
+    for (const c of document.querySelectorAll(".card")) {
+        c.style.display = c.dataset.search.includes(q) ? "" : "none";
+    }
+}
+</script>
+</body>
+</html>
+"#;
+
+/// An id safe to use as an anchor. Bytes other than ascii letters and digits are written "_" and their hex,
+/// so different names always give different ids.
+pub fn anchor(name: &str) -> String {
+    let mut res = "card-".to_string();
+    for b in name.bytes() {
+        match b.is_ascii_alphanumeric() {
+            true => res.push(b as char),
+            false => res.push_str(&format!("_{:02x}", b)),
+        }
+    }
+    res
+}
+
+pub fn data_html(d: &CData) -> String {
+    match d {
+        CData::S(s) => xml_escape(s),
+        CData::N(n) => n.to_string(),
+        CData::D(d) => d.to_string(),
+        CData::I(i) => i.to_string(),
+        CData::R(r) => format!("<a class=\"ref\" href=\"#{}\">{}</a>", anchor(r), xml_escape(r)),
+        CData::L(l) => {
+            let mut res = "<table class=\"list\">".to_string();
+            for v in l.iter() {
+                res.push_str(&format!("<tr><td>{}</td></tr>", data_html(v)));
+            }
+            res + "</table>"
+        }
+        CData::M(m) => {
+            let mut res = "<table class=\"map\">".to_string();
+            for (k, v) in m.iter() {
+                res.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>", xml_escape(k), data_html(v)));
+            }
+            res + "</table>"
+        }
+    }
+}
+
+pub fn card_html(c: &Card) -> String {
+    card_html_at(c, &anchor(&c.name))
+}
+
+fn card_html_at(c: &Card, id: &str) -> String {
+    let search = format!("{} {}", c.name, CData::M(c.data.clone().into())).to_lowercase();
+    let mut res = format!(
+        "<div class=\"card\" id=\"{id}\" data-search=\"{search}\">\n<h2><a href=\"#{id}\">{name}</a> <span class=\"num\">&times;{num}</span></h2>\n<table class=\"props\">\n",
+        id = id,
+        search = xml_escape(&search),
+        name = xml_escape(&c.name),
+        num = c.num,
+    );
+    for (k, v) in &c.data {
+        res.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", xml_escape(k), data_html(v)));
+    }
+    res + "</table>\n</div>\n"
+}
+
+/// Build a page listing every card, using the template's "{{title}}" and "{{cards}}" placeholders
+pub fn html_page(cards: &[Card], title: &str, template: &str) -> String {
+    // cards sharing a name get "-2", "-3" and so on, links going to the first
+    let mut seen = BTreeMap::new();
+    let cards: String = cards
+        .iter()
+        .map(|c| {
+            let n = seen.entry(c.name.as_str()).or_insert(0);
+            *n += 1;
+            match *n {
+                1 => card_html(c),
+                n => card_html_at(c, &format!("{}-{}", anchor(&c.name), n)),
+            }
+        })
+        .collect();
+    template
+        .replace("{{title}}", &xml_escape(title))
+        .replace("{{cards}}", &cards)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_cards;
+    #[test]
+    pub fn test_html_has_anchors_and_tables() {
+        let cards = parse_cards("2*\"Big Dog\":\n.jobs:[[wood,4]]\n.g:{s:\"<b>\"}").unwrap();
+        let page = html_page(&cards, "Dogs", DEFAULT_TEMPLATE);
+        assert!(page.contains("<title>Dogs</title>"));
+        assert!(page.contains("id=\"card-Big_20Dog\""));
+        assert!(page.contains("&times;2"));
+        assert!(page.contains("<table class=\"list\"><tr><td><table class=\"list\"><tr><td>wood</td></tr><tr><td>4</td></tr></table>"));
+        assert!(page.contains("<tr><th>s</th><td>&lt;b&gt;</td></tr>"));
+        assert_ne!(anchor("Fire Bolt"), anchor("Fire-Bolt"));
+        let cards = parse_cards("\"Fire Bolt\":\n\"Fire-Bolt\":\n\"Fire Bolt\":").unwrap();
+        let page = html_page(&cards, "Bolts", DEFAULT_TEMPLATE);
+        for id in ["card-Fire_20Bolt", "card-Fire_2dBolt", "card-Fire_20Bolt-2"] {
+            assert_eq!(page.matches(&format!("id=\"{}\"", id)).count(), 1, "{}", id);
+        }
+    }
+}
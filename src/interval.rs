@@ -0,0 +1,99 @@
+use serde_derive::*;
+use std::fmt::{self, Display};
+use std::convert::TryFrom;
+
+/// A range of whole numbers such as "3..5", including both ends.
+/// Either end may be left open, as in "..7" or "3..".
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Interval {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<i128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<i128>,
+}
+
+impl Interval {
+    /// None if start is after end
+    pub fn new(start: Option<i128>, end: Option<i128>) -> Option<Self> {
+        match (start, end) {
+            (Some(s), Some(e)) if s > e => None,
+            _ => Some(Interval { start, end }),
+        }
+    }
+
+    pub fn contains(&self, n: i128) -> bool {
+        self.start.map_or(true, |s| s <= n) && self.end.map_or(true, |e| n <= e)
+    }
+
+    /// True if some number is in both
+    pub fn overlaps(&self, other: &Interval) -> bool {
+        let below = |a: Option<i128>, b: Option<i128>| match (a, b) {
+            (Some(a), Some(b)) => a < b,
+            _ => false,
+        };
+        !below(self.end, other.start) && !below(other.end, self.start)
+    }
+
+    /// How many numbers it holds, None if an end is open or there are too many to count
+    pub fn count(&self) -> Option<usize> {
+        usize::try_from(self.end?.abs_diff(self.start?)).ok()?.checked_add(1)
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(s) = self.start {
+            write!(f, "{}", s)?;
+        }
+        write!(f, "..")?;
+        if let Some(e) = self.end {
+            write!(f, "{}", e)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::card::CData;
+    use crate::filter::Predicate;
+    use crate::parse_cards;
+    use crate::tokenize::{NUMBER_MAX, NUMBER_MIN};
+    #[test]
+    pub fn test_interval_values() {
+        let s = "@param play\nSpark 3..5:\n.targets:[..7,-2..,-5..-3]\n.jobs:[[[a]]]\n.jobs..:b";
+        let cards = parse_cards(s).unwrap();
+        let play = Interval::new(Some(3), Some(5)).unwrap();
+        assert_eq!(cards[0].data.get("play"), Some(&CData::I(play)));
+        assert!(play.contains(3) && play.contains(5) && !play.contains(6));
+        assert_eq!(play.count(), Some(3));
+        assert_eq!(Interval::new(Some(NUMBER_MIN), Some(NUMBER_MAX)).unwrap().count(), None);
+        let targets = cards[0].data.get("targets").unwrap().as_list().unwrap();
+        assert_eq!(targets[0], CData::I(Interval::new(None, Some(7)).unwrap()));
+        assert_eq!(
+            targets[2],
+            CData::I(Interval::new(Some(-5), Some(-3)).unwrap())
+        );
+        assert!(play.overlaps(&Interval::new(None, Some(3)).unwrap()));
+        assert!(!play.overlaps(&Interval::new(Some(-5), Some(-3)).unwrap()));
+        assert_eq!(
+            cards[0].data.get("jobs").unwrap().to_string(),
+            "[[[\"a\",\"b\"]]]"
+        );
+        assert_eq!(
+            cards[0].to_string(),
+            "1*Spark : \n.jobs:[[[\"a\",\"b\"]]]\n.play:3..5\n.targets:[..7,-2..,-5..-3]\n"
+        );
+        assert_eq!(serde_json::to_string(&targets[0]).unwrap(), r#"{"end":7}"#);
+        assert!(Predicate::parse("play=4").unwrap().matches(&cards[0]));
+        assert!(!Predicate::parse("play=6").unwrap().matches(&cards[0]));
+        let matches = |p: &str| Predicate::parse(p).unwrap().matches(&cards[0]);
+        assert!(matches("play<6") && matches("play<=5") && !matches("play<5") && !matches("play<10x"));
+        assert!(matches("play>2") && matches("play>=3") && !matches("play>3"));
+        assert!(matches("targets<0") && !matches("targets>7"));
+        for bad in ["A:\n.x:5..3", "A:\n.x:2d6..3", "A:\n.x:3..x", "A:\n.x:3..-"] {
+            assert!(parse_cards(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -1,9 +1,28 @@
+pub mod borrowed;
 pub mod card;
+#[cfg(feature = "util")]
+pub mod cli;
+pub mod dice;
+pub mod diff;
 pub mod err;
+pub mod filter;
+pub mod html;
+pub mod interval;
+pub mod lint;
+pub mod locale;
+pub mod markdown;
+pub mod merge;
+pub mod options;
+pub mod outline;
 pub mod parse;
+pub mod refs;
+pub mod render;
+#[cfg(feature = "sim")]
+pub mod simulate;
 pub mod tokenize;
 pub use card::{CData, Card};
 pub use err::{CardErr, CardRes};
+pub use options::ParserOptions;
 
 //use failure_derive::*;
 //use gobble::traits::*;
@@ -20,6 +39,35 @@ pub fn parse_cards(s: &str) -> CardRes<Vec<Card>> {
     p.parse_cards()
 }
 
+pub fn parse_cards_with(s: &str, opts: &ParserOptions) -> CardRes<Vec<Card>> {
+    opts.parse(s)
+}
+
+/// Parse several named sources as one deck.
+/// Consts, params and defaults carry on from each source to the next,
+/// and errors are marked with the name of the source they came from.
+pub fn parse_sources<N: AsRef<str>, S: AsRef<str>>(sources: &[(N, S)]) -> CardRes<Vec<Card>> {
+    Ok(parse_source_sections(sources, &ParserOptions::default())?.cards())
+}
+
+/// As parse_sources, but grouping the cards by section, and parsing with the options.
+/// The source name in the options is replaced by the name of each source in turn.
+pub fn parse_source_sections<N: AsRef<str>, S: AsRef<str>>(
+    sources: &[(N, S)],
+    opts: &ParserOptions,
+) -> CardRes<parse::Parsed> {
+    let mut res = parse::Parsed::default();
+    let mut p = opts.parser("");
+    for (n, (name, s)) in sources.iter().enumerate() {
+        let mut np = p.follow_on(s.as_ref());
+        np.options_mut().source_name = Some(name.as_ref().to_string());
+        np.hold_to_end(n + 1 < sources.len());
+        np.parse_into(&mut res)?;
+        p = np.follow_on("");
+    }
+    Ok(res)
+}
+
 pub fn load_cards<R: Read>(r: &mut R) -> CardRes<Vec<Card>> {
     let mut s = String::new();
     r.read_to_string(&mut s).map_err(|_| err::AtErr::FileErr)?;
@@ -41,6 +89,123 @@ pub mod test {
         assert_eq!(pets.get(1).unwrap(), &CData::S("cat".to_string()));
     }
     #[test]
+    pub fn test_sources_share_consts() {
+        let cds = parse_sources(&[
+            ("a.crd", "@const purple:\"Prap\"\n@def\n.speak:no\nporp:\n.do:$purple"),
+            ("b.crd", "doggo:\n.do:$purple"),
+        ])
+        .unwrap();
+        assert_eq!(cds.len(), 2);
+        assert_eq!(cds[1].data.get("do"), Some(&CData::S("Prap".to_string())));
+        assert_eq!(cds[1].data.get("speak"), Some(&CData::S("no".to_string())));
+        let e = parse_sources(&[("a.crd", "porp:\n.do:1"), ("b.crd", "doggo:\n.do:$purple")])
+            .unwrap_err();
+        assert!(e.to_string().starts_with("b.crd: "));
+    }
+    #[test]
+    pub fn test_consts_and_defaults_are_shared() {
+        let s = "@const big:{a:[1,2,3]}\n@def\n.d:[4,5]\nA:\n.x:$big\nB:\n.x:$big\n.x$b:6";
+        let cds = parse_cards(s).unwrap();
+        let list = |c: &Card, k: &str| match c.data.get(k) {
+            Some(CData::L(l)) => l.clone(),
+            _ => panic!("{} should be a list", k),
+        };
+        let map = |c: &Card| match c.data.get("x") {
+            Some(CData::M(m)) => m.clone(),
+            _ => panic!("x should be a map"),
+        };
+        assert!(std::sync::Arc::ptr_eq(&list(&cds[0], "d"), &list(&cds[1], "d")));
+        // B changed its copy, so no longer shares with A
+        assert!(!std::sync::Arc::ptr_eq(&map(&cds[0]), &map(&cds[1])));
+        assert_eq!(map(&cds[0]).len(), 1);
+        assert_eq!(map(&cds[1]).get("b"), Some(&CData::N(6)));
+    }
+    #[test]
+    pub fn test_duplicate_policies() {
+        let s = "@param cost\n2*Archer 3:\n.text:a\nMage 1:\nArcher 4:\n.power:2";
+        let sections = |dups| parse_source_sections(&[("a", s)], &ParserOptions::new().dups(dups));
+        let get = |dups| sections(dups).map(|p| p.cards());
+        assert_eq!(get(parse::DupPolicy::Allow).unwrap().len(), 3);
+        assert!(get(parse::DupPolicy::Error).is_err());
+        let warned = sections(parse::DupPolicy::Warn).unwrap();
+        assert_eq!(warned.warnings.len(), 1);
+        let files = [("a.crd", "Archer:"), ("b.crd", "Mage:\nArcher:")];
+        let warned = parse_source_sections(&files, &ParserOptions::new().dups(parse::DupPolicy::Warn)).unwrap();
+        assert_eq!(
+            warned.warnings[0].to_string(),
+            "b.crd:2:1: Card 'Archer' is already defined at a.crd:1:1"
+        );
+        let merged = get(parse::DupPolicy::Merge).unwrap();
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].data.get("cost"), Some(&CData::N(4)));
+        assert_eq!(merged[0].data.get("text"), Some(&CData::S("a".to_string())));
+        assert_eq!(merged[0].data.get("power"), Some(&CData::N(2)));
+        let summed = get(parse::DupPolicy::SumNum).unwrap();
+        assert_eq!(summed[0].num, 3);
+        assert_eq!(summed[0].data.get("cost"), Some(&CData::N(3)));
+
+        // only what the duplicate writes is merged, not its defaults, even from another source
+        let a = "@def\n.power:1\nArcher:\n.power:3\nMage:";
+        let b = "@def\n.power:0\nArcher:\n.cost:2\nMage:";
+        let opts = ParserOptions::new().dups(parse::DupPolicy::Merge);
+        let merged = parse_source_sections(&[("a", a), ("b", b)], &opts).unwrap().cards();
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].data.get("power"), Some(&CData::N(3)));
+        assert_eq!(merged[0].data.get("cost"), Some(&CData::N(2)));
+        assert_eq!(merged[1].data.get("power"), Some(&CData::N(1)));
+        let mut p = ParserOptions::new().dups(parse::DupPolicy::SumNum).parser(s);
+        let mut names = Vec::new();
+        while let Some(c) = p.next_card().unwrap() {
+            names.push((c.name, c.num));
+        }
+        assert_eq!(names, vec![("Archer".to_string(), 3), ("Mage".to_string(), 1)]);
+    }
+    #[test]
+    pub fn test_hostile_input_errors() {
+        let mut chain = String::from("@const c0:[[[[[[[[[[1]]]]]]]]]]\n");
+        for i in 1..10 {
+            chain.push_str(&format!("@const c{}:[[[[[[[[[[$c{}]]]]]]]]]]\n", i, i - 1));
+        }
+        let inputs = [
+            format!("A:\n.x:{}", "[".repeat(100000)),
+            format!("A:\n.x:{}1", "{a:".repeat(100000)),
+            format!("A:\n.x{}:1", ".".repeat(100000)),
+            format!("A:\n.x:{{a{}:1}}", "$b".repeat(100000)),
+            chain,
+            "A:\n.x:\"not closed".to_string(),
+            "@".to_string(),
+            "A:\n.x:-".to_string(),
+            "A:\n.x@:1".to_string(),
+        ];
+        for s in &inputs {
+            assert!(parse_cards(s).is_err());
+            assert!(borrowed::parse_cards_ref(s).is_err());
+        }
+        let deep = format!("A:\n.x{}:{}1{}", ".".repeat(30), "[".repeat(34), "]".repeat(34));
+        assert_eq!(parse_cards(&deep).unwrap()[0].data["x"].depth(), 64);
+    }
+    #[test]
+    pub fn test_errors_show_the_text() {
+        let e = parse_cards("A:\n.x:-d6").unwrap_err().to_string();
+        assert!(e.contains("Text(\"d6\")"), "{}", e);
+        let e = borrowed::parse_cards_ref("A:\n.x:-d6").unwrap_err().to_string();
+        assert!(e.contains("Text(\"d6\")"), "{}", e);
+        let mut p = parse::LineParser::new("Archer");
+        assert_eq!(p.consume(tokenize::CardToken::as_text, "Name").unwrap(), "Archer");
+    }
+    #[test]
+    pub fn test_u64_flags_and_i64_numbers() {
+        let s = "A:\n.flags:0xffff_ffff_ffff_ffff\n.low:- 0x8000_0000_0000_0000";
+        let cards = parse_cards(s).unwrap();
+        assert_eq!(cards[0].data.get("flags"), Some(&card::CData::N(u64::MAX as i128)));
+        assert_eq!(cards[0].data.get("low"), Some(&card::CData::N(i64::MIN as i128)));
+        let json = serde_json::to_string(&cards[0].data).unwrap();
+        assert_eq!(json, r#"{"flags":18446744073709551615,"low":-9223372036854775808}"#);
+        for bad in ["A:\n.x:0x1_0000_0000_0000_0000", "A:\n.x:- 0x8000_0000_0000_0001"] {
+            assert!(parse_cards(bad).is_err(), "{}", bad);
+        }
+    }
+    #[test]
     pub fn test_lists_work_both_ways() {
         let mut f = File::open("test_data/cards2_list.crd").unwrap();
         let cds = load_cards(&mut f).unwrap();
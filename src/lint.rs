@@ -0,0 +1,259 @@
+use crate::card::CData;
+use crate::err::AtErr;
+use crate::parse::{Line, LineParser};
+use crate::refs::find_refs;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display};
+use tokenate::Pos;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rule {
+    DuplicateName,
+    UnusedConst,
+    UnfilledParam,
+    RedundantDefault,
+    SimilarProperty,
+    EmptyList,
+    BrokenRef,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 7] = [
+        Rule::DuplicateName,
+        Rule::UnusedConst,
+        Rule::UnfilledParam,
+        Rule::RedundantDefault,
+        Rule::SimilarProperty,
+        Rule::EmptyList,
+        Rule::BrokenRef,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::DuplicateName => "duplicate-name",
+            Rule::UnusedConst => "unused-const",
+            Rule::UnfilledParam => "unfilled-param",
+            Rule::RedundantDefault => "redundant-default",
+            Rule::SimilarProperty => "similar-property",
+            Rule::EmptyList => "empty-list",
+            Rule::BrokenRef => "broken-ref",
+        }
+    }
+
+    pub fn from_name(s: &str) -> Option<Rule> {
+        Rule::ALL.iter().find(|r| r.name() == s).copied()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lint {
+    pub rule: Rule,
+    pub pos: Pos,
+    pub msg: String,
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{} {}: {}", self.pos.line, self.pos.col, self.rule.name(), self.msg)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LintConfig {
+    pub rules: BTreeSet<Rule>,
+    /// Property names this many edits apart (or differing only by case) are reported as similar
+    pub max_distance: usize,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        LintConfig {
+            rules: Rule::ALL.iter().copied().collect(),
+            max_distance: 1,
+        }
+    }
+}
+
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let sub = prev[j] + if ca == *cb { 0 } else { 1 };
+            curr.push(sub.min(prev[j + 1] + 1).min(curr[j] + 1));
+        }
+        prev = curr;
+    }
+    prev[b.len()]
+}
+
+fn has_empty_list(d: &CData) -> bool {
+    match d {
+        CData::L(l) => l.is_empty() || l.iter().any(has_empty_list),
+        CData::M(m) => m.values().any(has_empty_list),
+        _ => false,
+    }
+}
+
+struct Linter<'c> {
+    conf: &'c LintConfig,
+    res: Vec<Lint>,
+}
+
+impl<'c> Linter<'c> {
+    fn add(&mut self, rule: Rule, pos: Pos, msg: String) {
+        if self.conf.rules.contains(&rule) {
+            self.res.push(Lint { rule, pos, msg });
+        }
+    }
+}
+
+/// Check a card file for common mistakes, the lints are sorted by position
+pub fn lint(s: &str, conf: &LintConfig) -> Result<Vec<Lint>, AtErr> {
+    let mut l = Linter {
+        conf,
+        res: Vec::new(),
+    };
+    let mut p = LineParser::new(s);
+    let mut names: BTreeMap<String, Pos> = BTreeMap::new();
+    let mut consts: Vec<(String, Pos)> = Vec::new();
+    let mut props: BTreeMap<String, Pos> = BTreeMap::new();
+    // the current params, where they were declared, and how many have been filled
+    let mut params: (Vec<String>, Pos, usize) = (Vec::new(), Pos::new(), 0);
+    let mut default: BTreeMap<String, CData> = BTreeMap::new();
+    let mut in_card = false;
+    let mut refs: Vec<(String, Pos)> = Vec::new();
+    let mut add_refs = |d: &CData, pos: Pos| {
+        let mut found = Vec::new();
+        find_refs(d, String::new(), &mut found);
+        refs.extend(found.into_iter().map(|(_, r)| (r.to_string(), pos)));
+    };
+
+    let unfilled = |l: &mut Linter, params: &(Vec<String>, Pos, usize)| {
+        for name in params.0.iter().skip(params.2) {
+            l.add(Rule::UnfilledParam, params.1, format!("Param '{}' is never filled", name));
+        }
+    };
+
+    while let Some(ln) = p.next_line()? {
+        let pos = p.line_pos();
+        match ln {
+            Line::VarDef(name, v) => {
+                add_refs(&v, pos);
+                if has_empty_list(&v) {
+                    l.add(Rule::EmptyList, pos, format!("Const '{}' contains an empty list", name));
+                }
+                p.add_var(name.clone(), v);
+                consts.push((name, pos));
+            }
+            Line::Param(v) => {
+                unfilled(&mut l, &params);
+                for k in &v {
+                    props.entry(k.clone()).or_insert(pos);
+                }
+                params = (v, pos, 0);
+            }
+            Line::DefaultData(vals) => {
+                in_card = false;
+                params.2 = params.2.max(vals.len());
+                default = params.0.iter().cloned().zip(vals).collect();
+            }
+            Line::Section(_) | Line::Include(_) => in_card = false,
+            Line::Card { name, params: vals, .. } => {
+                in_card = true;
+                if let Some(first) = names.get(&name) {
+                    let msg = format!("Card '{}' was already defined on line {}", name, first.line);
+                    l.add(Rule::DuplicateName, pos, msg);
+                } else {
+                    names.insert(name.clone(), pos);
+                }
+                params.2 = params.2.max(vals.len());
+                for v in &vals {
+                    add_refs(v, pos);
+                }
+                for (k, v) in params.0.iter().zip(&vals) {
+                    if has_empty_list(v) {
+                        l.add(Rule::EmptyList, pos, format!("Param '{}' of '{}' is an empty list", k, name));
+                    }
+                    if default.get(k) == Some(v) {
+                        let msg = format!("Param '{}' of '{}' is the same as the default", k, name);
+                        l.add(Rule::RedundantDefault, pos, msg);
+                    }
+                }
+            }
+            Line::Data(k, path, v) => {
+                props.entry(k.clone()).or_insert(pos);
+                add_refs(&v, pos);
+                if has_empty_list(&v) {
+                    l.add(Rule::EmptyList, pos, format!("Property '{}' contains an empty list", k));
+                }
+                if in_card && path.is_empty() && default.get(&k) == Some(&v) {
+                    l.add(Rule::RedundantDefault, pos, format!("Property '{}' is the same as the default", k));
+                }
+                if !in_card && path.is_empty() {
+                    default.insert(k, v);
+                }
+            }
+        }
+    }
+    unfilled(&mut l, &params);
+
+    let used: BTreeSet<&str> = p.var_refs().iter().map(|(n, _)| n.as_str()).collect();
+    for (name, pos) in &consts {
+        if !used.contains(name.as_str()) {
+            l.add(Rule::UnusedConst, *pos, format!("Const '{}' is never used", name));
+        }
+    }
+
+    for (target, pos) in &refs {
+        if !names.contains_key(target) {
+            l.add(Rule::BrokenRef, *pos, format!("No card is named '{}'", target));
+        }
+    }
+
+    let props: Vec<(&String, &Pos)> = props.iter().collect();
+    for (i, (a, _)) in props.iter().enumerate() {
+        for (b, bpos) in &props[i + 1..] {
+            let close = a.to_lowercase() == b.to_lowercase()
+                || (a.chars().count() > 3 && edit_distance(a, b) <= conf.max_distance);
+            if close {
+                l.add(Rule::SimilarProperty, **bpos, format!("Property '{}' looks like '{}'", b, a));
+            }
+        }
+    }
+
+    l.res.sort_by_key(|a| (a.pos.line, a.pos.col));
+    Ok(l.res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    pub fn test_lint_rules() {
+        let s = "@param cost power\n@const unused:4\n@const used:[]\n@def 1\n.health:3\nArcher 2:\n.health:3\n.heath:[]\nArcher 1:\n.extra:$used\n.combo:[&Archer,&Mage]\n";
+        let lints = lint(s, &LintConfig::default()).unwrap();
+        let rules: Vec<(Rule, usize)> = lints.iter().map(|l| (l.rule, l.pos.line)).collect();
+        assert_eq!(
+            rules,
+            vec![
+                (Rule::UnfilledParam, 1),
+                (Rule::UnusedConst, 2),
+                (Rule::EmptyList, 3),
+                (Rule::RedundantDefault, 7),
+                (Rule::EmptyList, 8),
+                (Rule::SimilarProperty, 8),
+                (Rule::DuplicateName, 9),
+                (Rule::RedundantDefault, 9),
+                (Rule::EmptyList, 10),
+                (Rule::BrokenRef, 11),
+            ]
+        );
+        let conf = LintConfig {
+            rules: [Rule::DuplicateName].iter().copied().collect(),
+            max_distance: 1,
+        };
+        assert_eq!(lint(s, &conf).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,276 @@
+use crate::card::{CData, Card};
+use crate::err::CardErr;
+use crate::CardRes;
+use tokenate::Pos;
+
+/// One string to translate: the text of a card's property, and its translation so far
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub card: String,
+    pub key: String,
+    pub source: String,
+    pub translation: String,
+}
+
+/// Split "text@fr" into ("text", Some("fr"))
+pub fn split_lang(key: &str) -> (&str, Option<&str>) {
+    match key.find('@') {
+        Some(n) => (&key[..n], Some(&key[n + 1..])),
+        None => (key, None),
+    }
+}
+
+/// Every plain text property of the cards, with any existing translation into lang
+pub fn extract_strings(cards: &[Card], lang: &str) -> Vec<Entry> {
+    let mut res = Vec::new();
+    for c in cards {
+        for (k, v) in &c.data {
+            let source = match (split_lang(k), v) {
+                ((_, None), CData::S(s)) => s,
+                _ => continue,
+            };
+            let translation = match c.data.get(&format!("{}@{}", k, lang)) {
+                Some(CData::S(t)) => t.clone(),
+                _ => String::new(),
+            };
+            res.push(Entry {
+                card: c.name.clone(),
+                key: k.clone(),
+                source: source.clone(),
+                translation,
+            });
+        }
+    }
+    res
+}
+
+/// Set "key@lang" on the cards for every translated entry.
+/// Returns a message for each entry that no longer matches the cards.
+pub fn apply_translations(cards: &mut [Card], lang: &str, entries: &[Entry]) -> Vec<String> {
+    let mut res = Vec::new();
+    for e in entries.iter().filter(|e| !e.translation.is_empty()) {
+        let card = match cards.iter_mut().find(|c| c.name == e.card) {
+            Some(c) => c,
+            None => {
+                res.push(format!("{}.{}: no card named '{}'", e.card, e.key, e.card));
+                continue;
+            }
+        };
+        match card.data.get(&e.key) {
+            Some(CData::S(s)) if *s == e.source => {}
+            Some(_) => res.push(format!("{}.{}: the source text has changed", e.card, e.key)),
+            None => {
+                res.push(format!("{}.{}: no such property", e.card, e.key));
+                continue;
+            }
+        }
+        card.data
+            .insert(format!("{}@{}", e.key, lang), CData::S(e.translation.clone()));
+    }
+    res
+}
+
+fn csv_field(s: &str) -> String {
+    match s.contains(|c| ",\"\n\r".contains(c)) {
+        true => format!("\"{}\"", s.replace('"', "\"\"")),
+        false => s.to_string(),
+    }
+}
+
+pub fn to_csv(entries: &[Entry]) -> String {
+    let mut res = String::from("card,key,source,translation\n");
+    for e in entries {
+        let row = [&e.card, &e.key, &e.source, &e.translation];
+        let row: Vec<String> = row.iter().map(|s| csv_field(s)).collect();
+        res.push_str(&row.join(","));
+        res.push('\n');
+    }
+    res
+}
+
+fn line_err(line: usize, e: &'static str) -> crate::err::AtErr {
+    CardErr::S(e).at(Pos::at(0, line, 0))
+}
+
+/// Read a table written by to_csv, the first row is the header
+pub fn from_csv(s: &str) -> CardRes<Vec<Entry>> {
+    let mut rows: Vec<(usize, Vec<String>)> = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut row_line = 1;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push((row_line, std::mem::take(&mut row)));
+                line += 1;
+                row_line = line;
+            }
+            (_, c) => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+        }
+    }
+    if quoted {
+        return Err(line_err(row_line, "Quote not closed"));
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push((row_line, row));
+    }
+    rows.into_iter()
+        .skip(1)
+        .filter(|(_, r)| r.iter().any(|f| !f.is_empty()))
+        .map(|(n, mut r)| {
+            if r.len() < 3 || r.len() > 4 {
+                return Err(line_err(n, "Row should be card,key,source,translation"));
+            }
+            r.resize(4, String::new());
+            let mut r = r.into_iter();
+            let mut next = || r.next().unwrap_or_default();
+            Ok(Entry {
+                card: next(),
+                key: next(),
+                source: next(),
+                translation: next(),
+            })
+        })
+        .collect()
+}
+
+fn po_string(s: &str) -> String {
+    let s = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    format!("\"{}\"", s)
+}
+
+/// A gettext catalogue, each string's context is "Card.key"
+pub fn to_po(entries: &[Entry], lang: &str) -> String {
+    let mut res = format!(
+        "msgid \"\"\nmsgstr \"\"\n\"Language: {}\\n\"\n\"Content-Type: text/plain; charset=UTF-8\\n\"\n",
+        lang
+    );
+    for e in entries {
+        res.push_str(&format!(
+            "\nmsgctxt {}\nmsgid {}\nmsgstr {}\n",
+            po_string(&format!("{}.{}", e.card, e.key)),
+            po_string(&e.source),
+            po_string(&e.translation)
+        ));
+    }
+    res
+}
+
+fn po_unquote(s: &str, line: usize) -> CardRes<String> {
+    let s = s.trim();
+    if s.len() < 2 || !s.starts_with('"') || !s.ends_with('"') {
+        return Err(line_err(line, "Quoted string"));
+    }
+    let mut res = String::new();
+    let mut chars = s[1..s.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => res.push('\n'),
+                Some('t') => res.push('\t'),
+                Some(c) => res.push(c),
+                None => return Err(line_err(line, "Escaped character")),
+            },
+            c => res.push(c),
+        }
+    }
+    Ok(res)
+}
+
+/// Read a catalogue written by to_po, entries without a "Card.key" context are skipped
+pub fn from_po(s: &str) -> CardRes<Vec<Entry>> {
+    // context, id, str
+    let mut items: Vec<[String; 3]> = Vec::new();
+    let mut curr = [String::new(), String::new(), String::new()];
+    let mut field = None;
+    for (n, ln) in s.lines().enumerate() {
+        let ln = ln.trim();
+        // a new item starts with its msgctxt, or with msgid when it has no context
+        let (f, rest) = if let Some(r) = ln.strip_prefix("msgctxt ") {
+            if field.is_some() {
+                items.push(std::mem::take(&mut curr));
+            }
+            (0, r)
+        } else if let Some(r) = ln.strip_prefix("msgid ") {
+            if field.is_some() && field != Some(0) {
+                items.push(std::mem::take(&mut curr));
+            }
+            (1, r)
+        } else if let Some(r) = ln.strip_prefix("msgstr ") {
+            (2, r)
+        } else if ln.starts_with('"') {
+            match field {
+                Some(f) => (f, ln),
+                None => return Err(line_err(n + 1, "msgid before text")),
+            }
+        } else {
+            continue;
+        };
+        field = Some(f);
+        curr[f].push_str(&po_unquote(rest, n + 1)?);
+    }
+    items.push(curr);
+    Ok(items
+        .into_iter()
+        .filter_map(|[ctx, source, translation]| {
+            let dot = ctx.rfind('.')?;
+            Some(Entry {
+                card: ctx[..dot].to_string(),
+                key: ctx[dot + 1..].to_string(),
+                source,
+                translation,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_cards;
+    #[test]
+    pub fn test_translation_round_trip() {
+        let s = "Archer:\n.text:\"Shoots, far\"\n.text@fr:Tire\n.cost:3\nMage:\n.text@en:Zap\n.text:Zap\n.flavour:\"Old\nand wise\"";
+        let mut cards = parse_cards(s).unwrap();
+        assert_eq!(cards[0].data.get("text@fr"), Some(&CData::S("Tire".to_string())));
+        let fr = cards[0].localized("fr", "en");
+        assert_eq!(fr.data.get("text"), Some(&CData::S("Tire".to_string())));
+        assert_eq!(fr.data.len(), 2);
+        assert_eq!(cards[1].localized("fr", "en").data.get("text"), Some(&CData::S("Zap".to_string())));
+
+        let mut entries = extract_strings(&cards, "fr");
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].translation, "Tire");
+        assert_eq!(from_csv(&to_csv(&entries)).unwrap(), entries);
+        assert_eq!(from_po(&to_po(&entries, "fr")).unwrap(), entries);
+
+        entries[2].translation = "Vieux".to_string();
+        entries[1].source = "Changed".to_string();
+        entries[1].translation = "Zappe".to_string();
+        let problems = apply_translations(&mut cards, "fr", &entries);
+        assert_eq!(problems, vec!["Mage.flavour: the source text has changed".to_string()]);
+        assert_eq!(cards[1].data.get("text@fr"), Some(&CData::S("Vieux".to_string())));
+    }
+}
@@ -0,0 +1,85 @@
+use crate::card::{CData, Card};
+use crate::parse::Section;
+
+fn cell(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', "<br>")
+}
+
+/// Strings and numbers are written plainly, lists and maps in .crd value syntax
+pub fn cell_value(d: &CData) -> String {
+    match d {
+        CData::S(s) => cell(s),
+        d => cell(&d.to_string()),
+    }
+}
+
+/// Every property name used by any of the cards
+pub fn all_columns(cards: &[Card]) -> Vec<String> {
+    let mut res: Vec<String> = cards.iter().flat_map(|c| c.data.keys().cloned()).collect();
+    res.sort();
+    res.dedup();
+    res
+}
+
+/// A table with a row per card, the name and num columns always come first
+pub fn markdown_table(cards: &[Card], columns: &[String]) -> String {
+    let mut res = String::from("| name | num |");
+    for c in columns {
+        res.push_str(&format!(" {} |", cell(c)));
+    }
+    res.push_str("\n| --- | --- |");
+    for _ in columns {
+        res.push_str(" --- |");
+    }
+    res.push('\n');
+    for c in cards {
+        res.push_str(&format!("| {} | {} |", cell(&c.name), c.num));
+        for k in columns {
+            let v = c.data.get(k).map(cell_value).unwrap_or_default();
+            res.push_str(&format!(" {} |", v));
+        }
+        res.push('\n');
+    }
+    res
+}
+
+/// One table per section, each headed by its name.
+/// Without chosen columns each table shows the properties its own cards use.
+pub fn markdown_sections(sections: &[(Section, Vec<Card>)], columns: Option<&[String]>) -> String {
+    let mut res = String::new();
+    for (n, (sec, cards)) in sections.iter().enumerate() {
+        if !res.is_empty() {
+            res.push('\n');
+        }
+        match &sec.name {
+            Some(name) => res.push_str(&format!("## {}\n\n", name)),
+            None if sections.len() > 1 => res.push_str(&format!("## Group {}\n\n", n + 1)),
+            None => {}
+        }
+        let cols = match columns {
+            Some(c) => c.to_vec(),
+            None => all_columns(cards),
+        };
+        res.push_str(&markdown_table(cards, &cols));
+    }
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse::LineParser;
+    #[test]
+    pub fn test_markdown_section_tables() {
+        let s = "@section Troops\n@def\n.cost:1\n3*Archer:\n.jobs:[[wood,4]]\nKnight:\n@section Spells\nZap:\n.text:\"a|b\"";
+        let secs = LineParser::new(s).parse_sections().unwrap();
+        assert_eq!(secs.len(), 2);
+        assert_eq!(secs[0].0.name.as_deref(), Some("Troops"));
+        assert_eq!(secs[0].1.len(), 2);
+        let md = markdown_sections(&secs, None);
+        assert!(md.starts_with("## Troops\n\n| name | num | cost | jobs |\n"));
+        assert!(md.contains("| Archer | 3 | 1 | [[\"wood\",4]] |\n| Knight | 1 | 1 |  |\n"));
+        assert!(md.contains("## Spells"));
+        assert!(md.contains("| Zap | 1 | 1 | a\\|b |"));
+    }
+}
@@ -0,0 +1,202 @@
+use crate::card::{CData, Card};
+use crate::diff::PathKey;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+/// The same property of the same card was changed differently on both sides
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+    pub name: String,
+    pub path: Vec<PathKey>,
+    pub ours: Option<CData>,
+    pub theirs: Option<CData>,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for p in &self.path {
+            write!(f, "{}", p)?;
+        }
+        let side = |c: &Option<CData>| match c {
+            Some(c) => c.to_string(),
+            None => "(removed)".to_string(),
+        };
+        write!(f, ": ours {} , theirs {}", side(&self.ours), side(&self.theirs))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Merged {
+    pub cards: Vec<Card>,
+    pub conflicts: Vec<Conflict>,
+}
+
+fn by_name(cards: &[Card]) -> BTreeMap<&str, &Card> {
+    cards.iter().rev().map(|c| (c.name.as_str(), c)).collect()
+}
+
+/// Three way merge of resolved cards.
+/// Where both sides changed the same property, ours is kept and a conflict is recorded.
+pub fn merge_cards(base: &[Card], ours: &[Card], theirs: &[Card]) -> Merged {
+    let (bm, om, tm) = (by_name(base), by_name(ours), by_name(theirs));
+    let mut names: Vec<&str> = Vec::new();
+    for c in ours.iter().chain(theirs) {
+        if !names.contains(&c.name.as_str()) {
+            names.push(&c.name);
+        }
+    }
+
+    let mut res = Merged {
+        cards: Vec::new(),
+        conflicts: Vec::new(),
+    };
+    for name in names {
+        let (b, o, t) = (bm.get(name), om.get(name), tm.get(name));
+        match (o, t) {
+            (Some(o), Some(t)) => {
+                let empty = Card::new(name.to_string(), 0);
+                let b = b.copied().unwrap_or(&empty);
+                res.cards.push(merge_card(b, o, t, &mut res.conflicts));
+            }
+            (Some(c), None) | (None, Some(c)) => match b {
+                None => res.cards.push((*c).clone()),
+                Some(b) if same_card(b, c) => {}
+                Some(_) => {
+                    res.conflicts.push(Conflict {
+                        name: name.to_string(),
+                        path: Vec::new(),
+                        ours: o.map(|c| (*c).clone().flatten()),
+                        theirs: t.map(|c| (*c).clone().flatten()),
+                    });
+                    res.cards.push((*c).clone());
+                }
+            },
+            (None, None) => {}
+        }
+    }
+    res
+}
+
+fn same_card(a: &Card, b: &Card) -> bool {
+    a.num == b.num && a.data == b.data && a.tags == b.tags
+}
+
+pub fn merge_card(base: &Card, ours: &Card, theirs: &Card, conflicts: &mut Vec<Conflict>) -> Card {
+    let num = |c: &Card| CData::N(c.num as i128);
+    let mut path = vec![PathKey::Key("num".to_string())];
+    let n = match merge_data(
+        &ours.name,
+        &mut path,
+        Some(&num(base)),
+        Some(&num(ours)),
+        Some(&num(theirs)),
+        conflicts,
+    ) {
+        Some(CData::N(n)) => n as usize,
+        _ => ours.num,
+    };
+    path.clear();
+    let data = merge_map(&ours.name, &mut path, &base.data, &ours.data, &theirs.data, conflicts);
+    // keep tags both sides kept, and any either side added
+    let tags = ours
+        .tags
+        .iter()
+        .chain(&theirs.tags)
+        .filter(|t| !base.tags.contains(*t) || (ours.tags.contains(*t) && theirs.tags.contains(*t)))
+        .cloned();
+    Card::build(ours.name.clone(), n, data).with_tags(tags)
+}
+
+fn merge_map(
+    name: &str,
+    path: &mut Vec<PathKey>,
+    base: &BTreeMap<String, CData>,
+    ours: &BTreeMap<String, CData>,
+    theirs: &BTreeMap<String, CData>,
+    conflicts: &mut Vec<Conflict>,
+) -> BTreeMap<String, CData> {
+    let mut keys: Vec<&String> = base.keys().chain(ours.keys()).chain(theirs.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    let mut res = BTreeMap::new();
+    for k in keys {
+        path.push(PathKey::Key(k.clone()));
+        if let Some(v) = merge_data(name, path, base.get(k), ours.get(k), theirs.get(k), conflicts) {
+            res.insert(k.clone(), v);
+        }
+        path.pop();
+    }
+    res
+}
+
+fn merge_data(
+    name: &str,
+    path: &mut Vec<PathKey>,
+    base: Option<&CData>,
+    ours: Option<&CData>,
+    theirs: Option<&CData>,
+    conflicts: &mut Vec<Conflict>,
+) -> Option<CData> {
+    if ours == theirs || base == theirs {
+        return ours.cloned();
+    }
+    if base == ours {
+        return theirs.cloned();
+    }
+    match (base, ours, theirs) {
+        (b, Some(CData::M(o)), Some(CData::M(t))) => {
+            let empty = BTreeMap::new();
+            let b = match b {
+                Some(CData::M(b)) => b,
+                _ => &empty,
+            };
+            Some(CData::M(merge_map(name, path, b, o, t, conflicts).into()))
+        }
+        (Some(CData::L(b)), Some(CData::L(o)), Some(CData::L(t)))
+            if b.len() == o.len() && o.len() == t.len() =>
+        {
+            let mut res = Vec::new();
+            for i in 0..b.len() {
+                path.push(PathKey::Index(i));
+                res.extend(merge_data(name, path, b.get(i), o.get(i), t.get(i), conflicts));
+                path.pop();
+            }
+            Some(CData::L(res.into()))
+        }
+        _ => {
+            conflicts.push(Conflict {
+                name: name.to_string(),
+                path: path.clone(),
+                ours: ours.cloned(),
+                theirs: theirs.cloned(),
+            });
+            ours.or(theirs).cloned()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_cards;
+    #[test]
+    pub fn test_merge_independent_changes() {
+        let base = parse_cards("Archer:\n.cost:3\n.str:4\nMage:\n.cost:5").unwrap();
+        let ours = parse_cards("Archer:\n.cost:2\n.str:4\nMage:\n.cost:5\nKnight:\n.cost:6").unwrap();
+        let theirs = parse_cards("2*Archer:\n.cost:3\n.str:5\nMage:\n.cost:4").unwrap();
+        let m = merge_cards(&base, &ours, &theirs);
+        assert!(m.conflicts.is_empty());
+        assert_eq!(m.cards[0].num, 2);
+        assert_eq!(m.cards[0].data.get("cost"), Some(&CData::N(2)));
+        assert_eq!(m.cards[0].data.get("str"), Some(&CData::N(5)));
+        assert_eq!(m.cards[1].data.get("cost"), Some(&CData::N(4)));
+        assert_eq!(m.cards[2].name, "Knight");
+
+        let theirs = parse_cards("Archer:\n.cost:1\n.str:4\nMage:\n.cost:5").unwrap();
+        let m = merge_cards(&base, &ours, &theirs);
+        assert_eq!(m.conflicts.len(), 1);
+        assert_eq!(m.conflicts[0].path, vec![PathKey::Key("cost".to_string())]);
+        assert_eq!(m.cards[0].data.get("cost"), Some(&CData::N(2)));
+    }
+}
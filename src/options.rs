@@ -0,0 +1,138 @@
+use crate::card::{CData, Card};
+use crate::parse::{DupPolicy, LineParser};
+use crate::CardRes;
+use std::collections::BTreeMap;
+
+/// Settings for a LineParser, so different games can share one way of parsing.
+///
+/// ```
+/// use card_format::{CData, ParserOptions};
+/// let opts = ParserOptions::new()
+///     .constant("gold", CData::N(3))
+///     .params(&["cost"])
+///     .default_value("health", CData::N(1))
+///     .source_name("deck.crd");
+/// let cards = opts.parse("Archer $gold:").unwrap();
+/// assert_eq!(cards[0].data.get("cost"), Some(&CData::N(3)));
+/// assert_eq!(cards[0].data.get("health"), Some(&CData::N(1)));
+/// ```
+#[derive(Clone, Debug)]
+pub struct ParserOptions {
+    pub consts: BTreeMap<String, CData>,
+    pub params: Vec<String>,
+    pub defaults: BTreeMap<String, CData>,
+    /// If false an unknown "$var" becomes the string "$var" with a warning, instead of an error
+    pub strict_vars: bool,
+    /// If false values beyond the declared params are dropped with a warning, instead of an error
+    pub strict_params: bool,
+    /// Allow "@include "file.crd"", read relative to the including file
+    pub includes: bool,
+    /// Errors are marked with this name
+    pub source_name: Option<String>,
+    pub dups: DupPolicy,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        ParserOptions {
+            consts: BTreeMap::new(),
+            params: Vec::new(),
+            defaults: BTreeMap::new(),
+            strict_vars: true,
+            strict_params: true,
+            includes: false,
+            source_name: None,
+            dups: DupPolicy::Allow,
+        }
+    }
+}
+
+impl ParserOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn constant(mut self, k: &str, v: CData) -> Self {
+        self.consts.insert(k.to_string(), v);
+        self
+    }
+
+    pub fn params(mut self, p: &[&str]) -> Self {
+        self.params = p.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    pub fn default_value(mut self, k: &str, v: CData) -> Self {
+        self.defaults.insert(k.to_string(), v);
+        self
+    }
+
+    pub fn strict_vars(mut self, b: bool) -> Self {
+        self.strict_vars = b;
+        self
+    }
+
+    pub fn strict_params(mut self, b: bool) -> Self {
+        self.strict_params = b;
+        self
+    }
+
+    pub fn includes(mut self, b: bool) -> Self {
+        self.includes = b;
+        self
+    }
+
+    pub fn source_name(mut self, s: &str) -> Self {
+        self.source_name = Some(s.to_string());
+        self
+    }
+
+    pub fn dups(mut self, d: DupPolicy) -> Self {
+        self.dups = d;
+        self
+    }
+
+    pub fn parser<'a>(&self, s: &'a str) -> LineParser<'a> {
+        LineParser::with_options(s, self.clone())
+    }
+
+    pub fn parse(&self, s: &str) -> CardRes<Vec<Card>> {
+        self.parser(s).parse_cards()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse::Parsed;
+    #[test]
+    pub fn test_lenient_and_includes() {
+        let s = "@param cost\nArcher $silver 4:";
+        assert!(ParserOptions::new().parse(s).is_err());
+        let mut p = ParserOptions::new().strict_vars(false).strict_params(false).parser(s);
+        let mut res = Parsed::default();
+        p.parse_into(&mut res).unwrap();
+        assert_eq!(res.warnings.len(), 2);
+        let cards = res.cards();
+        assert_eq!(cards[0].data.get("cost"), Some(&CData::S("$silver".to_string())));
+        assert_eq!(cards[0].data.len(), 1);
+
+        let main = std::fs::read_to_string("test_data/include_main.crd").unwrap();
+        let e = ParserOptions::new().parse(&main).unwrap_err();
+        assert!(e.to_string().contains("Includes are not enabled"));
+        let opts = ParserOptions::new()
+            .includes(true)
+            .source_name("test_data/include_main.crd");
+        let mut res = Parsed::default();
+        opts.parser(&main).parse_into(&mut res).unwrap();
+        assert_eq!(res.included, vec!["test_data/include_shared.crd".to_string()]);
+        let cards = res.cards();
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].name, "Archer");
+        assert_eq!(cards[1].data.get("cost"), Some(&CData::N(2)));
+        assert_eq!(cards[1].data.get("health"), Some(&CData::N(5)));
+
+        let e = opts.parse("Archer:\n.a:$nope").unwrap_err();
+        assert!(e.to_string().starts_with("test_data/include_main.crd: "));
+    }
+}
@@ -0,0 +1,150 @@
+use crate::card::{CData, Card};
+use crate::err::AtErr;
+use crate::parse::{Line, LineParser};
+use std::collections::{BTreeMap, BTreeSet};
+use tokenate::Pos;
+
+/// Where a card's lines begin and end in the source, "end" is exclusive
+#[derive(Clone, Debug, PartialEq)]
+pub struct CardSpan {
+    pub name: String,
+    pub start: Pos,
+    pub end: Pos,
+}
+
+impl CardSpan {
+    pub fn contains_line(&self, line: usize) -> bool {
+        line >= self.start.line && line < self.end.line
+    }
+}
+
+/// The structure of a card file, as needed by editors.
+#[derive(Debug, Default)]
+pub struct Outline {
+    pub cards: Vec<CardSpan>,
+    pub consts: BTreeMap<String, (Pos, CData)>,
+    pub var_refs: Vec<(String, Pos)>,
+    pub props: BTreeSet<String>,
+    /// The fully resolved cards, in the same order as "cards", if the whole file parsed
+    pub resolved: Vec<Card>,
+    pub err: Option<AtErr>,
+}
+
+fn end_pos(s: &str) -> Pos {
+    Pos::at(s.len(), s.split('\n').count() + 1, 0)
+}
+
+impl Outline {
+    /// Parse s once, both for its layout and its resolved cards
+    pub fn new(s: &str) -> Self {
+        let mut res = Outline::default();
+        let mut p = LineParser::new(s);
+        p.keep_lines();
+        loop {
+            match p.next_card() {
+                Ok(Some(c)) => res.resolved.push(c),
+                Ok(None) => break,
+                Err(e) => {
+                    res.err = Some(e);
+                    break;
+                }
+            }
+        }
+        let mut open: Option<CardSpan> = None;
+        for (pos, ln) in p.kept_lines() {
+            if let Line::Data(k, _, _) = ln {
+                res.props.insert(k.clone());
+                continue;
+            }
+            if let Some(mut c) = open.take() {
+                c.end = *pos;
+                res.cards.push(c);
+            }
+            match ln {
+                Line::Card { name, .. } => {
+                    open = Some(CardSpan {
+                        name: name.clone(),
+                        start: *pos,
+                        end: *pos,
+                    })
+                }
+                Line::VarDef(name, v) => {
+                    res.consts.insert(name.clone(), (*pos, v.clone()));
+                }
+                _ => {}
+            }
+        }
+        if let Some(mut c) = open.take() {
+            c.end = end_pos(s);
+            res.cards.push(c);
+        }
+        res.var_refs = p.var_refs().to_vec();
+        if res.err.is_some() || res.resolved.len() != res.cards.len() {
+            res.resolved.clear();
+        }
+        res
+    }
+
+    pub fn card_at(&self, line: usize) -> Option<usize> {
+        self.cards.iter().position(|c| c.contains_line(line))
+    }
+
+    /// The name of a "$var" reference covering the given line and column
+    pub fn var_ref_at(&self, line: usize, col: usize) -> Option<&str> {
+        self.var_refs
+            .iter()
+            .find(|(n, p)| p.line == line && col >= p.col && col <= p.col + n.chars().count())
+            .map(|(n, _)| n.as_str())
+    }
+}
+
+/// The column of p counted in utf16 code units from the start of its line, as editors using LSP count them
+pub fn utf16_col(s: &str, p: Pos) -> usize {
+    let i = p.i.min(s.len());
+    let start = s[..i].rfind('\n').map_or(0, |n| n + 1);
+    s[start..i].encode_utf16().count()
+}
+
+/// The column, counted in chars from 1 as Pos does, of the utf16 offset col on the 1 based line
+pub fn char_col(s: &str, line: usize, col: usize) -> usize {
+    let text = s.split('\n').nth(line.saturating_sub(1)).unwrap_or("");
+    let mut units = 0;
+    let mut chars = 0;
+    for c in text.chars() {
+        if units >= col {
+            break;
+        }
+        units += c.len_utf16();
+        chars += 1;
+    }
+    chars + 1
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    pub fn test_outline_finds_cards_and_consts() {
+        let s = "@param color\n@const purple:\"Prap\"\n@def\n.speak:no\n\nporp $purple:\n.do:paint\n\ndoggo:\n.pets:[fish]\n";
+        let o = Outline::new(s);
+        assert!(o.err.is_none());
+        assert_eq!(o.cards.len(), 2);
+        assert_eq!(o.cards[0].start.line, 6);
+        assert_eq!(o.card_at(7), Some(0));
+        assert_eq!(o.card_at(10), Some(1));
+        assert_eq!(o.consts["purple"].0.line, 2);
+        assert_eq!(o.var_ref_at(6, 7), Some("purple"));
+        assert_eq!(o.resolved[0].data.get("speak"), Some(&CData::S("no".to_string())));
+        assert!(o.props.contains("pets"));
+
+        let s = "@const a:1\nX:\n.t:[\"\u{1F600}\",$a]";
+        let o = Outline::new(s);
+        let (name, pos) = &o.var_refs[0];
+        // the '$' is the 9th char, but the smiley before it is 2 utf16 units
+        assert_eq!((name.as_str(), pos.line, pos.col), ("a", 3, 9));
+        assert_eq!(utf16_col(s, *pos), 9);
+        assert_eq!(char_col(s, 3, 9), 9);
+        assert_eq!(o.var_ref_at(3, char_col(s, 3, 10)), Some("a"));
+        assert!(Outline::new("A:\n.x:$nope").resolved.is_empty());
+    }
+}
@@ -1,9 +1,15 @@
 use crate::card::*;
-use crate::err::{expected, CardErr};
-use crate::tokenize::{CardToken, CardTokenizer};
+use crate::dice::Dice;
+use crate::err::{expected, line_col, AtErr, CardErr};
+use crate::interval::Interval;
+use crate::options::ParserOptions;
+use crate::tokenize::{in_number_range, token_text, CardToken, CardTokenizer};
 use crate::CardRes;
-use std::collections::BTreeMap;
-use tokenate::{TErr, Token, TokenRes};
+use std::borrow::{Borrow, Cow};
+use std::convert::TryFrom;
+use std::collections::{BTreeMap, VecDeque};
+use std::path::Path;
+use tokenate::{Pos, TErr, Token, TokenRes};
 
 macro_rules! resop {
     ($e:expr) => {
@@ -20,45 +26,413 @@ macro_rules! resop {
     };
 }
 
+/// How deep "@include" may nest, to stop files including each other forever
+pub const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// How deep lists, maps and paths may nest in one value, to stop hostile input overflowing the stack
+pub const MAX_NESTING: usize = 64;
+
 pub type CVec = Vec<(String, CData)>;
 
+/// Cards between one "@def" or "@section" and the next share a Section
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Section {
+    pub name: Option<String>,
+    pub group: usize,
+}
+
+/// What to do when a card has the same name as an earlier one
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum DupPolicy {
+    #[default]
+    Allow,
+    Error,
+    /// Keep both, but add a warning
+    Warn,
+    /// The properties written on later cards are added to the first, replacing any it had.
+    /// Defaults only fill what the first card still lacks.
+    Merge,
+    /// Add the later num to the first card, ignoring the rest
+    SumNum,
+}
+
+impl DupPolicy {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "allow" => Some(DupPolicy::Allow),
+            "error" => Some(DupPolicy::Error),
+            "warn" => Some(DupPolicy::Warn),
+            "merge" => Some(DupPolicy::Merge),
+            "sum" => Some(DupPolicy::SumNum),
+            _ => None,
+        }
+    }
+}
+
+/// Cards grouped by section, and any warnings found while parsing
+#[derive(Debug)]
+pub struct Parsed<C = Card> {
+    pub sections: Vec<(Section, Vec<C>)>,
+    pub warnings: Vec<AtErr>,
+    /// The paths of every file read by "@include"
+    pub included: Vec<String>,
+}
+
+impl<C> Default for Parsed<C> {
+    fn default() -> Self {
+        Parsed {
+            sections: Vec::new(),
+            warnings: Vec::new(),
+            included: Vec::new(),
+        }
+    }
+}
+
+impl<C> Parsed<C> {
+    pub fn cards(self) -> Vec<C> {
+        self.sections.into_iter().flat_map(|(_, v)| v).collect()
+    }
+
+    /// Add a card to the last section, or a new one if the section has changed
+    pub fn push(&mut self, sec: &Section, c: C) {
+        match self.sections.last_mut() {
+            Some((s, v)) if s == sec => v.push(c),
+            _ => self.sections.push((sec.clone(), vec![c])),
+        }
+    }
+}
+
+/// The values a LineParser builds: CData, or CDataRef borrowing its text from the source
+pub trait ParsedData<'a>: Clone {
+    /// The string used for names and keys
+    type Str: Ord + Clone + Borrow<str> + From<Cow<'a, str>>;
+    /// True to leave Text tokens empty and read the text from the source, see CardTokenizer::borrowing
+    const BORROWS: bool;
+    /// A value given in the ParserOptions
+    fn from_owned(d: CData) -> Self;
+    fn text(s: Cow<'a, str>) -> Self;
+    fn card_ref(s: Cow<'a, str>) -> Self;
+    fn number(n: i128) -> Self;
+    fn dice(d: Dice) -> Self;
+    fn interval(i: Interval) -> Self;
+    fn list(l: Vec<Self>) -> Self;
+    fn map(m: DataMap<'a, Self>) -> Self;
+    fn depth(&self) -> usize;
+    fn add_at_path(&mut self, c: Self, path: &[CDPathNode]) -> Result<(), CardErr>;
+    fn build_from_path(c: Self, path: &[CDPathNode]) -> Self;
+}
+
+/// The cards a LineParser builds: Card, or CardRef borrowing its text from the source
+pub trait ParsedCard<'a>: Sized {
+    type Data: ParsedData<'a>;
+    fn build(num: usize, name: StrOf<'a, Self>, data: DataMap<'a, Self::Data>, tags: Vec<StrOf<'a, Self>>) -> Self;
+    fn name(&self) -> &StrOf<'a, Self>;
+    fn data_mut(&mut self) -> &mut DataMap<'a, Self::Data>;
+    fn fill_defaults(&mut self, defaults: &DataMap<'a, Self::Data>);
+    /// Add the properties and tags of a later card of the same name, replacing any already here
+    fn merge(&mut self, later: Self);
+    fn add_num(&mut self, later: Self);
+    /// Read the included file at path into the parser's pending cards
+    fn include(p: &mut LineParser<'a, Self>, path: String) -> CardRes<()>;
+}
+
+/// The str of a name or key, whichever string type holds it
+fn str_of<S: Borrow<str>>(s: &S) -> &str {
+    s.borrow()
+}
+
+pub type DataMap<'a, D> = BTreeMap<<D as ParsedData<'a>>::Str, D>;
+pub type DataOf<'a, C> = <C as ParsedCard<'a>>::Data;
+pub type StrOf<'a, C> = <DataOf<'a, C> as ParsedData<'a>>::Str;
+pub type LineOf<'a, C> = Line<StrOf<'a, C>, DataOf<'a, C>>;
+/// The params and tags of a card header
+type Header<'a, C> = (Vec<DataOf<'a, C>>, Vec<StrOf<'a, C>>);
+
+impl<'a> ParsedData<'a> for CData {
+    type Str = String;
+    const BORROWS: bool = false;
+    fn from_owned(d: CData) -> Self {
+        d
+    }
+    fn text(s: Cow<'a, str>) -> Self {
+        CData::S(s.into_owned())
+    }
+    fn card_ref(s: Cow<'a, str>) -> Self {
+        CData::R(s.into_owned())
+    }
+    fn number(n: i128) -> Self {
+        CData::N(n)
+    }
+    fn dice(d: Dice) -> Self {
+        CData::D(d)
+    }
+    fn interval(i: Interval) -> Self {
+        CData::I(i)
+    }
+    fn list(l: Vec<Self>) -> Self {
+        CData::L(l.into())
+    }
+    fn map(m: BTreeMap<String, Self>) -> Self {
+        CData::M(m.into())
+    }
+    fn depth(&self) -> usize {
+        CData::depth(self)
+    }
+    fn add_at_path(&mut self, c: Self, path: &[CDPathNode]) -> Result<(), CardErr> {
+        CData::add_at_path(self, c, path)
+    }
+    fn build_from_path(c: Self, path: &[CDPathNode]) -> Self {
+        CData::build_from_path(c, path)
+    }
+}
+
+impl<'a> ParsedCard<'a> for Card {
+    type Data = CData;
+    fn build(num: usize, name: String, data: BTreeMap<String, CData>, tags: Vec<String>) -> Self {
+        Card::build(name, num, data).with_tags(tags)
+    }
+    fn name(&self) -> &String {
+        &self.name
+    }
+    fn data_mut(&mut self) -> &mut BTreeMap<String, CData> {
+        &mut self.data
+    }
+    fn fill_defaults(&mut self, defaults: &BTreeMap<String, CData>) {
+        Card::fill_defaults(self, defaults)
+    }
+    fn merge(&mut self, later: Self) {
+        self.data.extend(later.data);
+        self.tags.extend(later.tags);
+    }
+    fn add_num(&mut self, later: Self) {
+        self.num += later.num;
+    }
+    fn include(p: &mut LineParser<'a, Self>, path: String) -> CardRes<()> {
+        p.include_file(path)
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
-pub enum Line {
-    DefaultData(Vec<CData>),
-    VarDef(String, CData),
-    Param(Vec<String>),
+pub enum Line<S = String, D = CData> {
+    DefaultData(Vec<D>),
+    VarDef(S, D),
+    Param(Vec<S>),
     Card {
         num: usize,
-        name: String,
-        params: Vec<CData>,
+        name: S,
+        params: Vec<D>,
+        tags: Vec<S>,
     },
-    Data(String, Vec<CDPathNode>, CData),
+    Data(S, Vec<CDPathNode>, D),
+    Section(S),
+    Include(S),
 }
 
-pub struct LineParser<'a> {
+/// Reads cards from a source. It builds Cards unless asked for CardRefs, see borrowed::RefParser.
+pub struct LineParser<'a, C: ParsedCard<'a> = Card> {
     tk: CardTokenizer<'a>,
-    vars: BTreeMap<String, CData>,
+    vars: DataMap<'a, C::Data>,
     peek: Option<Token<'a, CardToken>>,
-    default: BTreeMap<String, CData>,
-    params: Vec<String>,
-    curr_card: Option<Card>,
+    default: DataMap<'a, C::Data>,
+    params: Vec<StrOf<'a, C>>,
+    curr_card: Option<C>,
+    line_pos: Pos,
+    var_refs: Vec<(StrOf<'a, C>, Pos)>,
+    section: Section,
+    card_section: Section,
+    last_section: Section,
+    opts: ParserOptions,
+    /// Card names seen so far with where they were defined, only kept for the Error and Warn policies
+    names: BTreeMap<StrOf<'a, C>, String>,
+    /// Under Merge and SumNum, cards are held back until the end so later duplicates can join them
+    held: Vec<(Section, C)>,
+    held_at: BTreeMap<StrOf<'a, C>, usize>,
+    hold_to_end: bool,
+    warnings: Vec<AtErr>,
+    included: Vec<String>,
+    depth: usize,
+    nesting: usize,
+    pending: VecDeque<(Section, C)>,
+    kept_lines: Option<Vec<(Pos, LineOf<'a, C>)>>,
 }
 
 impl<'a> LineParser<'a> {
     pub fn new(s: &'a str) -> Self {
-        Self {
+        Self::with_options(s, ParserOptions::default())
+    }
+
+    /// A parser starting with the consts, params and defaults set in the options
+    pub fn with_options(s: &'a str, opts: ParserOptions) -> Self {
+        Self::building(s, opts)
+    }
+
+    /// Continue parsing from a new source, keeping the consts, params and defaults set so far
+    pub fn follow_on<'b>(self, s: &'b str) -> LineParser<'b> {
+        LineParser {
             tk: CardTokenizer::new(s),
-            vars: BTreeMap::new(),
+            vars: self.vars,
             peek: None,
-            default: BTreeMap::new(),
-            params: Vec::new(),
+            default: self.default,
+            params: self.params,
+            curr_card: self.curr_card,
+            line_pos: Pos::new(),
+            var_refs: Vec::new(),
+            section: self.section,
+            card_section: self.card_section,
+            last_section: self.last_section,
+            opts: self.opts,
+            names: self.names,
+            held: self.held,
+            held_at: self.held_at,
+            hold_to_end: false,
+            warnings: self.warnings,
+            included: self.included,
+            depth: self.depth,
+            nesting: 0,
+            pending: self.pending,
+            kept_lines: self.kept_lines.map(|_| Vec::new()),
+        }
+    }
+
+    /// Parse the rest of an included file, adding its cards to the pending queue.
+    /// Consts, params, defaults and sections flow into the included file and back out again.
+    fn include_file(&mut self, path: String) -> CardRes<()> {
+        let full = match self.opts.source_name.as_ref().and_then(|n| Path::new(n).parent()) {
+            Some(dir) => dir.join(&path),
+            None => Path::new(&path).to_path_buf(),
+        };
+        let full = full.display().to_string();
+        let text = std::fs::read_to_string(&full)
+            .map_err(|e| CardErr::Include(full.clone(), e.to_string()).at(self.line_pos))?;
+
+        if let Some(done) = self.finish_card(self.card_section.clone()) {
+            self.pending.push_back(done);
+        }
+        self.included.push(full.clone());
+
+        let mut sub = LineParser::with_options(&text, self.opts.clone());
+        sub.opts.source_name = Some(full);
+        sub.depth = self.depth + 1;
+        self.swap_state(&mut sub);
+        let res = sub.include_cards(&mut self.pending);
+        self.swap_state(&mut sub);
+        res
+    }
+
+    fn include_cards(&mut self, pending: &mut VecDeque<(Section, Card)>) -> CardRes<()> {
+        while let Some(c) = self.next_card()? {
+            pending.push_back((self.last_section.clone(), c));
+        }
+        Ok(())
+    }
+
+    /// Exchange everything that carries across files, leaving the tokenizer and current card
+    fn swap_state(&mut self, other: &mut LineParser) {
+        std::mem::swap(&mut self.vars, &mut other.vars);
+        std::mem::swap(&mut self.default, &mut other.default);
+        std::mem::swap(&mut self.params, &mut other.params);
+        std::mem::swap(&mut self.section, &mut other.section);
+        std::mem::swap(&mut self.names, &mut other.names);
+        std::mem::swap(&mut self.held, &mut other.held);
+        std::mem::swap(&mut self.held_at, &mut other.held_at);
+        std::mem::swap(&mut self.warnings, &mut other.warnings);
+        std::mem::swap(&mut self.included, &mut other.included);
+    }
+}
+
+impl<'a, C: ParsedCard<'a>> LineParser<'a, C> {
+    /// A parser for any kind of card, starting with the consts, params and defaults set in the options
+    pub fn building(s: &'a str, opts: ParserOptions) -> Self {
+        let owned = |m: &BTreeMap<String, CData>| {
+            m.iter()
+                .map(|(k, v)| (Cow::<str>::Owned(k.clone()).into(), C::Data::from_owned(v.clone())))
+                .collect()
+        };
+        let tk = match C::Data::BORROWS {
+            true => CardTokenizer::borrowing(s),
+            false => CardTokenizer::new(s),
+        };
+        Self {
+            tk,
+            vars: owned(&opts.consts),
+            peek: None,
+            default: owned(&opts.defaults),
+            params: opts.params.iter().map(|p| Cow::<str>::Owned(p.clone()).into()).collect(),
             curr_card: None,
+            line_pos: Pos::new(),
+            var_refs: Vec::new(),
+            section: Section::default(),
+            card_section: Section::default(),
+            last_section: Section::default(),
+            opts,
+            names: BTreeMap::new(),
+            held: Vec::new(),
+            held_at: BTreeMap::new(),
+            hold_to_end: false,
+            warnings: Vec::new(),
+            included: Vec::new(),
+            depth: 0,
+            nesting: 0,
+            pending: VecDeque::new(),
+            kept_lines: None,
         }
     }
-    pub fn add_var(&mut self, k: String, v: CData) {
+
+    /// The section of the card most recently returned by next_card
+    pub fn last_section(&self) -> &Section {
+        &self.last_section
+    }
+
+    pub fn set_dup_policy(&mut self, dups: DupPolicy) {
+        self.opts.dups = dups;
+    }
+
+    pub fn dup_policy(&self) -> DupPolicy {
+        self.opts.dups
+    }
+
+    /// Under Merge and SumNum, keep the held cards back at the end of this source too,
+    /// for when another source will follow on and may repeat their names
+    pub fn hold_to_end(&mut self, hold: bool) {
+        self.hold_to_end = hold;
+    }
+
+    pub fn options(&self) -> &ParserOptions {
+        &self.opts
+    }
+
+    pub fn options_mut(&mut self) -> &mut ParserOptions {
+        &mut self.opts
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<AtErr> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    pub fn add_var(&mut self, k: StrOf<'a, C>, v: C::Data) {
         self.vars.insert(k, v);
     }
 
+    /// The position of the start of the most recent line
+    pub fn line_pos(&self) -> Pos {
+        self.line_pos
+    }
+
+    /// Every "$var" used in a value so far, with the position of its '$'
+    pub fn var_refs(&self) -> &[(StrOf<'a, C>, Pos)] {
+        &self.var_refs
+    }
+
+    /// Keep each line read by next_card from now on, for editors wanting the layout as well as the cards
+    pub fn keep_lines(&mut self) {
+        self.kept_lines.get_or_insert_with(Vec::new);
+    }
+
+    /// The lines kept since keep_lines, with the position each started at
+    pub fn kept_lines(&self) -> &[(Pos, LineOf<'a, C>)] {
+        self.kept_lines.as_deref().unwrap_or_default()
+    }
+
     pub fn next_token(&mut self) -> TokenRes<'a, CardToken> {
         match self.peek.take() {
             Some(c) => Ok(Some(c)),
@@ -81,6 +455,15 @@ impl<'a> LineParser<'a> {
         }
     }
 
+    /// The text of the next token, which must be Text. It borrows from the source unless it had escapes.
+    pub fn text(&mut self, exp: &'static str) -> CardRes<Cow<'a, str>> {
+        let t = resop!(self.next_token(), exp);
+        match t.value {
+            CardToken::Text(_) => Ok(token_text(&t)),
+            _ => expected(exp, &t),
+        }
+    }
+
     pub fn maybe_consume<T, F: Fn(&CardToken) -> Option<T>>(&mut self, f: F) -> CardRes<Option<T>> {
         let t = resop!(self.peek_token());
         match f(&t.value) {
@@ -92,7 +475,7 @@ impl<'a> LineParser<'a> {
         }
     }
 
-    pub fn peek_token<'b>(&'b mut self) -> Result<Option<&Token<'a, CardToken>>, TErr> {
+    pub fn peek_token(&mut self) -> Result<Option<&Token<'a, CardToken>>, TErr> {
         if self.peek.is_none() {
             self.peek = self.tk.next()?;
         }
@@ -116,15 +499,15 @@ impl<'a> LineParser<'a> {
                 }
                 CardToken::Dollar => {
                     self.unpeek();
-                    let at = self.consume(|v| v.as_text(), "Index or Key")?;
-                    res.push(CDPathNode::AtKey(at));
+                    let at = self.text("Index or Key")?;
+                    res.push(CDPathNode::AtKey(at.into_owned()));
                 }
                 _ => return Ok(res),
             }
         }
     }
 
-    pub fn peek_value<'b>(&'b mut self) -> Result<Option<&'b CardToken>, TErr> {
+    pub fn peek_value(&mut self) -> Result<Option<&CardToken>, TErr> {
         Ok(Some(&resop!(self.peek_token()).value))
     }
 
@@ -132,6 +515,15 @@ impl<'a> LineParser<'a> {
         self.peek = None;
     }
 
+    /// Go n levels deeper into a value, failing past MAX_NESTING
+    fn nest(&mut self, n: usize) -> CardRes<()> {
+        self.nesting += n;
+        match self.nesting > MAX_NESTING {
+            true => Err(CardErr::S("Values nested too deeply").at(self.tk.peek_pos())),
+            false => Ok(()),
+        }
+    }
+
     pub fn breaks(&mut self) -> Result<(), TErr> {
         while let Some(p) = self.peek_token()? {
             if p.value == CardToken::Break {
@@ -143,25 +535,28 @@ impl<'a> LineParser<'a> {
         Ok(())
     }
 
-    pub fn map_properties(&mut self) -> CardRes<BTreeMap<String, CData>> {
-        let mut res = BTreeMap::new();
+    pub fn map_properties(&mut self) -> CardRes<DataMap<'a, C::Data>> {
+        let mut res: DataMap<'a, C::Data> = BTreeMap::new();
         loop {
             let pk = match self.peek_token()? {
                 None => return Ok(res),
-                Some(p) => p,
+                Some(p) => p.clone(),
             };
-            match pk.value.clone() {
-                CardToken::Text(k) => {
+            match pk.value {
+                CardToken::Text(_) => {
                     self.unpeek();
+                    let k: StrOf<'a, C> = token_text(&pk).into();
                     let path = self.cdata_path()?;
                     self.consume(|t| t.eq_option(&CardToken::Colon), "Colon")?;
+                    self.nest(path.len())?;
                     let v = self.value()?;
-                    match res.get_mut(&k) {
+                    self.nesting -= path.len();
+                    match res.get_mut(str_of(&k)) {
                         Some(parent) => parent
                             .add_at_path(v, &path)
                             .map_err(|e| e.at(self.tk.peek_pos()))?,
                         None => {
-                            res.insert(k.to_string(), CData::build_from_path(v, &path));
+                            res.insert(k, C::Data::build_from_path(v, &path));
                         }
                     }
                 }
@@ -169,12 +564,12 @@ impl<'a> LineParser<'a> {
                 CardToken::WiggleClose => {
                     return Ok(res);
                 }
-                _ => return Err(CardErr::Expected("Card Property").got(pk)),
+                _ => return Err(CardErr::Expected("Card Property").got(&pk)),
             }
         }
     }
 
-    pub fn values(&mut self, list: bool) -> CardRes<Vec<CData>> {
+    pub fn values(&mut self, list: bool) -> CardRes<Vec<C::Data>> {
         let mut res = Vec::new();
         loop {
             let pk = match self.peek_token()? {
@@ -183,7 +578,10 @@ impl<'a> LineParser<'a> {
             };
             match pk.value {
                 CardToken::Dollar
+                | CardToken::Amp
                 | CardToken::Number(_)
+                | CardToken::Dice(_)
+                | CardToken::Interval(_)
                 | CardToken::Minus
                 | CardToken::Text(_)
                 | CardToken::WiggleOpen
@@ -192,6 +590,7 @@ impl<'a> LineParser<'a> {
                 CardToken::Comma => {
                     self.unpeek();
                 }
+                CardToken::Percent if !list => return Ok(res),
                 CardToken::Break | CardToken::Colon => match list {
                     true => self.unpeek(),
                     false => return Ok(res),
@@ -205,59 +604,107 @@ impl<'a> LineParser<'a> {
         }
     }
 
-    pub fn value(&mut self) -> CardRes<CData> {
+    pub fn value(&mut self) -> CardRes<C::Data> {
         let t = resop!(self.next_token(), "Value");
         match &t.value {
             CardToken::Dollar => {
-                let v = self.consume(|v| v.as_text(), "Variable Name")?;
-                match self.vars.get(&v) {
+                let v: StrOf<'a, C> = self.text("Variable Name")?.into();
+                self.var_refs.push((v.clone(), t.start));
+                match self.vars.get(str_of(&v)) {
+                    Some(v) if self.nesting + v.depth() > MAX_NESTING => {
+                        Err(CardErr::S("Values nested too deeply").got(&t))
+                    }
                     Some(v) => Ok(v.clone()),
+                    None if !self.opts.strict_vars => {
+                        self.warn(CardErr::Expected("Var does not exist").got(&t));
+                        Ok(C::Data::text(Cow::Owned(format!("${}", str_of(&v)))))
+                    }
                     None => expected("Var does not exist", &t),
                 }
             }
-            CardToken::Number(n) => Ok(CData::N(*n)),
-            CardToken::Minus => self
-                .consume(|v| v.as_number(), "Number")
-                .map(|n| CData::N(-n)),
-            CardToken::Text(tx) => Ok(CData::S(tx.clone())),
+            CardToken::Number(n) => Ok(C::Data::number(*n)),
+            CardToken::Dice(d) => Ok(C::Data::dice(*d)),
+            CardToken::Interval(i) => Ok(C::Data::interval(**i)),
+            CardToken::Minus => match in_number_range(-self.consume(|v| v.as_number(), "Number")?) {
+                Some(n) => Ok(C::Data::number(n)),
+                None => expected("Number in range", &t),
+            },
+            CardToken::Text(_) => Ok(C::Data::text(token_text(&t))),
+            CardToken::Amp => self.text("Card Name").map(C::Data::card_ref),
             CardToken::SquareOpen => {
+                self.nest(1)?;
                 let v = self.values(true)?;
                 self.consume(|t| t.eq_option(&CardToken::SquareClose), "Close List")?;
-                Ok(CData::L(v))
+                self.nesting -= 1;
+                Ok(C::Data::list(v))
             }
             CardToken::WiggleOpen => {
+                self.nest(1)?;
                 let mp = self.map_properties()?;
                 self.consume(|t| t.eq_option(&CardToken::WiggleClose), "Close List")?;
-                Ok(CData::M(mp))
+                self.nesting -= 1;
+                Ok(C::Data::map(mp))
             }
             _ => expected("A Value", &t),
         }
     }
 
-    pub fn next_line(&mut self) -> CardRes<Option<Line>> {
+    /// "%tag" names, which may come anywhere among the params
+    pub fn tags(&mut self) -> CardRes<Vec<StrOf<'a, C>>> {
+        let mut res = Vec::new();
+        while self.maybe_consume(|t| t.eq_option(&CardToken::Percent))?.is_some() {
+            res.push(self.text("Tag Name")?.into());
+        }
+        Ok(res)
+    }
+
+    /// The params and tags following a card's name, up to the optional colon
+    fn card_header_rest(&mut self) -> CardRes<Header<'a, C>> {
+        let mut tags = self.tags()?;
+        let mut params = Vec::new();
+        loop {
+            let vals = self.values(false)?;
+            let more = self.tags()?;
+            if vals.is_empty() && more.is_empty() {
+                break;
+            }
+            params.extend(vals);
+            tags.extend(more);
+        }
+        self.maybe_consume(|t| t.eq_option(&CardToken::Colon))?;
+        Ok((params, tags))
+    }
+
+    pub fn next_line(&mut self) -> CardRes<Option<LineOf<'a, C>>> {
+        self.nesting = 0;
         self.breaks()?;
         let nt = resop!(self.peek_token()).clone();
+        self.line_pos = nt.start;
         match nt.value {
             CardToken::Number(num) => {
                 self.unpeek();
+                let num = match usize::try_from(num) {
+                    Ok(n) => n,
+                    Err(_) => return expected("A card count of 0 or more", &nt),
+                };
                 self.consume(|t| t.eq_option(&CardToken::Star), "Star")?;
-                let name = self.consume(|t| t.as_text(), "Card Name")?;
-                let params = self.values(false)?;
-                self.maybe_consume(|t| t.eq_option(&CardToken::Colon))?;
+                let name = self.text("Card Name")?.into();
+                let (params, tags) = self.card_header_rest()?;
                 Ok(Some(Line::Card {
                     name,
-                    num: num as usize,
+                    num,
                     params,
+                    tags,
                 }))
             }
-            CardToken::Text(name) => {
+            CardToken::Text(_) => {
                 self.unpeek();
-                let params = self.values(false)?;
-                self.maybe_consume(|t| t.eq_option(&CardToken::Colon))?;
+                let (params, tags) = self.card_header_rest()?;
                 Ok(Some(Line::Card {
-                    name: name.clone(),
+                    name: token_text(&nt).into(),
                     num: 1,
                     params,
+                    tags,
                 }))
             }
             CardToken::KwParam => {
@@ -265,8 +712,8 @@ impl<'a> LineParser<'a> {
                 self.unpeek();
                 let mut pp = Vec::new();
                 while let Some(tk) = self.peek_token()? {
-                    if let CardToken::Text(t) = &tk.value {
-                        pp.push(t.to_string());
+                    if let CardToken::Text(_) = &tk.value {
+                        pp.push(token_text(tk).into());
                         self.unpeek();
                     } else {
                         return Ok(Some(Line::Param(pp)));
@@ -280,97 +727,215 @@ impl<'a> LineParser<'a> {
                 self.maybe_consume(|t| t.eq_option(&CardToken::Colon))?;
                 Ok(Some(Line::DefaultData(v)))
             }
+            CardToken::KwSection => {
+                self.unpeek();
+                let name = self.text("Section Name")?.into();
+                self.maybe_consume(|t| t.eq_option(&CardToken::Colon))?;
+                Ok(Some(Line::Section(name)))
+            }
+            CardToken::KwInclude => {
+                self.unpeek();
+                let path = self.text("File Name")?.into();
+                Ok(Some(Line::Include(path)))
+            }
             CardToken::KwConst => {
                 self.unpeek();
-                let name = self.consume(|t| t.as_text(), "Var Name")?;
+                let name = self.text("Var Name")?.into();
                 self.maybe_consume(|t| t.eq_option(&CardToken::Colon))?;
                 let v = self.value()?;
                 Ok(Some(Line::VarDef(name, v)))
             }
             CardToken::Dot => {
                 self.unpeek();
-                let name = self.consume(CardToken::as_text, "Property Name")?;
+                let mut name = self.text("Property Name")?;
+                if let Some(lang) = self.tk.lang_suffix()? {
+                    name = Cow::Owned(format!("{}@{}", name, lang.value));
+                }
                 let path = self.cdata_path()?;
                 //let post = self.maybe_consume(CardToken::as_dots)?.unwrap_or(0);
                 self.consume(|v| v.eq_option(&CardToken::Colon), "Colon")?;
+                self.nest(path.len())?;
                 let v = self.value()?;
-                Ok(Some(Line::Data(name, path, v)))
+                Ok(Some(Line::Data(name.into(), path, v)))
             }
             _ => expected("An entry ", &nt),
         }
     }
 
-    fn fill_params(&mut self, v: Vec<CData>) -> CardRes<BTreeMap<String, CData>> {
+    fn fill_params(&mut self, v: Vec<C::Data>) -> CardRes<DataMap<'a, C::Data>> {
         let mut defdata = BTreeMap::new();
         for (n, p) in v.into_iter().enumerate() {
-            defdata.insert(
-                self.params
-                    .get(n)
-                    .ok_or(CardErr::S("Not enough params defined before").at(self.tk.peek_pos()))?
-                    .to_string(),
-                p,
-            );
+            let k = match self.params.get(n) {
+                Some(k) => k.clone(),
+                None => {
+                    let e = CardErr::S("Not enough params defined before").at(self.tk.peek_pos());
+                    if self.opts.strict_params {
+                        return Err(e);
+                    }
+                    self.warn(e);
+                    break;
+                }
+            };
+            defdata.insert(k, p);
         }
         Ok(defdata)
     }
 
-    pub fn next_card(&mut self) -> CardRes<Option<Card>> {
+    fn include(&mut self, path: StrOf<'a, C>) -> CardRes<()> {
+        if !self.opts.includes {
+            return Err(CardErr::S("Includes are not enabled").at(self.line_pos));
+        }
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            return Err(CardErr::S("Includes nested too deeply").at(self.line_pos));
+        }
+        C::include(self, str_of(&path).to_string())
+    }
+
+    /// Fill the defaults of the current card and return it with its section.
+    /// Under Merge and SumNum it is held back instead, or joined to an earlier card of the same name.
+    fn finish_card(&mut self, sec: Section) -> Option<(Section, C)> {
+        let mut c = self.curr_card.take()?;
+        if let DupPolicy::Allow | DupPolicy::Error | DupPolicy::Warn = self.opts.dups {
+            c.fill_defaults(&self.default);
+            return Some((sec, c));
+        }
+        match self.held_at.get(str_of(c.name())) {
+            Some(&i) => {
+                let prev = &mut self.held[i].1;
+                match self.opts.dups {
+                    DupPolicy::Merge => prev.merge(c),
+                    _ => prev.add_num(c),
+                }
+            }
+            None => {
+                c.fill_defaults(&self.default);
+                self.held_at.insert(c.name().clone(), self.held.len());
+                self.held.push((sec, c));
+            }
+        }
+        None
+    }
+
+    /// "file:line:col", or "line:col" without a source name
+    fn place(&self, p: Pos) -> String {
+        match &self.opts.source_name {
+            Some(n) => format!("{}:{}", n, line_col(p)),
+            None => line_col(p),
+        }
+    }
+
+    /// Keep a warning, marked with the source name as errors are
+    fn warn(&mut self, e: AtErr) {
+        let e = match &self.opts.source_name {
+            Some(n) => e.in_file(n),
+            None => e,
+        };
+        self.warnings.push(e);
+    }
+
+    /// The next complete card, errors are marked with the source name if there is one
+    pub fn next_card(&mut self) -> CardRes<Option<C>> {
+        let res = self.next_card_inner();
+        match &self.opts.source_name {
+            Some(n) => res.map_err(|e| e.in_file(n)),
+            None => res,
+        }
+    }
+
+    fn next_card_inner(&mut self) -> CardRes<Option<C>> {
         self.breaks()?;
         loop {
+            if let Some((sec, c)) = self.pending.pop_front() {
+                self.last_section = sec;
+                return Ok(Some(c));
+            }
             let ln = match self.next_line()? {
                 Some(ln) => ln,
-                None => match self.curr_card.take() {
-                    Some(mut curr) => {
-                        curr.fill_defaults(&self.default);
-                        return Ok(Some(curr));
+                None => {
+                    if let Some((sec, c)) = self.finish_card(self.card_section.clone()) {
+                        self.last_section = sec;
+                        return Ok(Some(c));
                     }
-                    None => return Ok(None),
-                },
+                    if self.depth > 0 || self.hold_to_end || self.held.is_empty() {
+                        return Ok(None);
+                    }
+                    self.held_at.clear();
+                    self.pending.extend(self.held.drain(..));
+                    continue;
+                }
             };
+            if let Some(kept) = &mut self.kept_lines {
+                kept.push((self.line_pos, ln.clone()));
+            }
             match ln {
                 Line::DefaultData(params) => {
-                    if let Some(curr) = &mut self.curr_card {
-                        curr.fill_defaults(&self.default);
-                    }
-
+                    let done = self.finish_card(self.card_section.clone());
                     self.default = self.fill_params(params)?;
-                    if let Some(tres) = self.curr_card.take() {
-                        return Ok(Some(tres));
+                    self.section.group += 1;
+                    if let Some((sec, c)) = done {
+                        self.last_section = sec;
+                        return Ok(Some(c));
                     }
                 }
+                Line::Section(name) => {
+                    self.section = Section {
+                        name: Some(str_of(&name).to_string()),
+                        group: self.section.group + 1,
+                    };
+                    if let Some((sec, c)) = self.finish_card(self.card_section.clone()) {
+                        self.last_section = sec;
+                        return Ok(Some(c));
+                    }
+                }
+                Line::Include(path) => self.include(path)?,
                 Line::VarDef(name, val) => {
                     self.vars.insert(name, val);
                 }
                 Line::Param(v) => {
                     self.params = v;
                 }
-                Line::Card { num, name, params } => {
-                    let tres = self.curr_card.take();
-
-                    self.curr_card = Some(Card {
-                        num,
-                        name,
-                        data: self.fill_params(params)?,
-                    });
-
-                    if let Some(mut curr) = tres {
-                        curr.fill_defaults(&self.default);
-                        return Ok(Some(curr));
+                Line::Card {
+                    num,
+                    name,
+                    params,
+                    tags,
+                } => {
+                    if let DupPolicy::Error | DupPolicy::Warn = self.opts.dups {
+                        if let Some(first) = self.names.get(str_of(&name)) {
+                            let e = CardErr::Duplicate(str_of(&name).to_string(), first.clone()).at(self.line_pos);
+                            match self.opts.dups {
+                                DupPolicy::Error => return Err(e),
+                                _ => self.warn(e),
+                            }
+                        } else {
+                            let here = self.place(self.line_pos);
+                            self.names.insert(name.clone(), here);
+                        }
+                    }
+                    let done = self.finish_card(self.card_section.clone());
+                    self.card_section = self.section.clone();
+
+                    let data = self.fill_params(params)?;
+                    self.curr_card = Some(C::build(num, name, data, tags));
+
+                    if let Some((sec, c)) = done {
+                        self.last_section = sec;
+                        return Ok(Some(c));
                     }
                 }
                 Line::Data(k, path, val) => {
                     let tree = match &mut self.curr_card {
-                        Some(r) => &mut r.data,
+                        Some(r) => r.data_mut(),
                         None => &mut self.default,
                     };
 
-                    match tree.get_mut(&k) {
+                    match tree.get_mut(str_of(&k)) {
                         Some(c) => {
                             c.add_at_path(val, &path)
                                 .map_err(|e| e.at(self.tk.peek_pos()))?;
                         }
                         None => {
-                            let v = CData::build_from_path(val, &path);
+                            let v = C::Data::build_from_path(val, &path);
                             tree.insert(k, v);
                         }
                     }
@@ -379,19 +944,32 @@ impl<'a> LineParser<'a> {
         }
     }
 
-    pub fn parse_cards(&mut self) -> CardRes<Vec<Card>> {
-        let mut res = Vec::new();
+    pub fn parse_cards(&mut self) -> CardRes<Vec<C>> {
+        Ok(self.parse_sections()?.into_iter().flat_map(|(_, v)| v).collect())
+    }
+
+    /// Parse all the cards, grouping neighbours that share a Section
+    pub fn parse_sections(&mut self) -> CardRes<Vec<(Section, Vec<C>)>> {
+        let mut res = Parsed::default();
+        self.parse_into(&mut res)?;
+        Ok(res.sections)
+    }
+
+    /// Parse the remaining cards onto the end of res, moving any warnings across too
+    pub fn parse_into(&mut self, res: &mut Parsed<C>) -> CardRes<()> {
         while let Some(c) = self.next_card()? {
-            res.push(c);
+            res.push(&self.last_section, c);
         }
-        Ok(res)
+        res.warnings.append(&mut self.warnings);
+        res.included.append(&mut self.included);
+        Ok(())
     }
 }
 
 #[cfg(gods)]
 mod tests {
     use super::*;
-    use std::collections::BTreeMap;
+    use std::collections::{BTreeMap, BTreeSet};
     use std::io::Read;
     #[test]
     fn it_works() {
@@ -0,0 +1,139 @@
+use crate::card::{CData, Card};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+/// A reference to a card that is not in the deck
+#[derive(Clone, Debug, PartialEq)]
+pub struct BrokenRef {
+    pub card: String,
+    /// Where the reference is inside the card, eg "combo.1"
+    pub path: String,
+    pub target: String,
+}
+
+impl Display for BrokenRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}.{} refers to missing card '{}'",
+            self.card, self.path, self.target
+        )
+    }
+}
+
+/// Push every reference inside d, with path extended to reach it
+pub fn find_refs<'a>(d: &'a CData, path: String, res: &mut Vec<(String, &'a str)>) {
+    match d {
+        CData::R(r) => res.push((path, r)),
+        CData::L(l) => {
+            for (i, v) in l.iter().enumerate() {
+                find_refs(v, format!("{}.{}", path, i), res);
+            }
+        }
+        CData::M(m) => {
+            for (k, v) in m.iter() {
+                find_refs(v, format!("{}.{}", path, k), res);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Every reference in the card, with its dotted path
+pub fn card_refs(c: &Card) -> Vec<(String, &str)> {
+    let mut res = Vec::new();
+    for (k, v) in &c.data {
+        find_refs(v, k.clone(), &mut res);
+    }
+    res
+}
+
+/// Check that every reference names a card in the deck
+pub fn check_refs(cards: &[Card]) -> Vec<BrokenRef> {
+    let index = CardIndex::new(cards);
+    let mut res = Vec::new();
+    for c in cards {
+        for (path, target) in card_refs(c) {
+            if index.get(target).is_none() {
+                res.push(BrokenRef {
+                    card: c.name.clone(),
+                    path,
+                    target: target.to_string(),
+                });
+            }
+        }
+    }
+    res
+}
+
+/// Look up cards by name, to follow references between them.
+/// If names repeat, the first card with the name is used.
+pub struct CardIndex<'a> {
+    cards: BTreeMap<&'a str, &'a Card>,
+}
+
+impl<'a> CardIndex<'a> {
+    pub fn new(cards: &'a [Card]) -> Self {
+        let mut res = BTreeMap::new();
+        for c in cards {
+            res.entry(c.name.as_str()).or_insert(c);
+        }
+        CardIndex { cards: res }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&'a Card> {
+        self.cards.get(name).copied()
+    }
+
+    /// The card a reference value points to
+    pub fn follow(&self, d: &CData) -> Option<&'a Card> {
+        self.get(d.as_ref_name()?)
+    }
+
+    /// The cards referred to anywhere inside the card's property
+    pub fn follow_prop(&self, c: &Card, k: &str) -> Vec<&'a Card> {
+        let mut refs = Vec::new();
+        if let Some(v) = c.data.get(k) {
+            find_refs(v, k.to_string(), &mut refs);
+        }
+        refs.into_iter().filter_map(|(_, r)| self.get(r)).collect()
+    }
+
+    /// The cards that refer to the named card
+    pub fn referenced_by(&self, name: &str) -> Vec<&'a Card> {
+        self.cards
+            .values()
+            .filter(|c| card_refs(c).iter().any(|(_, r)| *r == name))
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_cards;
+    #[test]
+    pub fn test_refs_resolve() {
+        let s = "Archer:\n.upgrade:&\"Master Archer\"\n\"Master Archer\":\n.combo:[&Archer,&Mage]";
+        let cards = parse_cards(s).unwrap();
+        assert_eq!(cards[0].data.get("upgrade"), Some(&CData::R("Master Archer".to_string())));
+        assert_eq!(
+            check_refs(&cards),
+            vec![BrokenRef {
+                card: "Master Archer".to_string(),
+                path: "combo.1".to_string(),
+                target: "Mage".to_string(),
+            }]
+        );
+        let index = CardIndex::new(&cards);
+        let up = index.follow(cards[0].data.get("upgrade").unwrap()).unwrap();
+        assert_eq!(up.name, "Master Archer");
+        assert_eq!(index.follow_prop(up, "combo").len(), 1);
+        assert_eq!(index.referenced_by("Archer")[0].name, "Master Archer");
+        assert_eq!(cards[1].to_string(), "1*\"Master Archer\" : \n.combo:[&Archer,&Mage]\n");
+        assert_eq!(cards[0].data.get("upgrade").unwrap().to_string(), "&\"Master Archer\"");
+        let json = serde_json::to_string(cards[0].data.get("upgrade").unwrap()).unwrap();
+        assert_eq!(json, r#"{"ref":"Master Archer"}"#);
+    }
+}
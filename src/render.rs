@@ -0,0 +1,220 @@
+use crate::card::{CData, Card};
+
+/// Sizes are all in mm
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    pub paper: (f64, f64),
+    pub card: (f64, f64),
+    pub bleed: f64,
+    pub margin: f64,
+    pub cut_marks: bool,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            paper: Layout::A4,
+            card: (63., 88.),
+            bleed: 3.,
+            margin: 5.,
+            cut_marks: true,
+        }
+    }
+}
+
+impl Layout {
+    pub const A4: (f64, f64) = (210., 297.);
+    pub const LETTER: (f64, f64) = (215.9, 279.4);
+
+    /// The size of each card including its bleed
+    pub fn cell(&self) -> (f64, f64) {
+        (self.card.0 + 2. * self.bleed, self.card.1 + 2. * self.bleed)
+    }
+
+    /// How many cards fit across and down a page
+    pub fn grid(&self) -> (usize, usize) {
+        let (cw, ch) = self.cell();
+        let fit = |page: f64, c: f64| (((page - 2. * self.margin) / c).floor() as usize).max(1);
+        (fit(self.paper.0, cw), fit(self.paper.1, ch))
+    }
+}
+
+pub fn xml_escape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&apos;"),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// Replace every "{{path}}" in the template with the card's value at that path.
+/// Strings are written without quotes, missing values are left empty.
+pub fn fill_template(template: &str, card: &Card) -> String {
+    let mut res = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(e) => start + e,
+            None => break,
+        };
+        res.push_str(&rest[..start]);
+        match card.lookup(rest[start + 2..end].trim()) {
+            Some(CData::S(s)) => res.push_str(&xml_escape(&s)),
+            Some(v) => res.push_str(&xml_escape(&v.to_string())),
+            None => {}
+        }
+        rest = &rest[end + 2..];
+    }
+    res.push_str(rest);
+    res
+}
+
+fn attr<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let pat = format!(" {}=\"", name);
+    let start = tag.find(&pat)? + pat.len();
+    let len = tag[start..].find('"')?;
+    Some(&tag[start..start + len])
+}
+
+fn without_attr(tag: &str, name: &str) -> String {
+    match attr(tag, name) {
+        Some(v) => tag.replacen(&format!(" {}=\"{}\"", name, v), "", 1),
+        None => tag.to_string(),
+    }
+}
+
+/// Move a filled card svg into place on the sheet.
+/// The root svg tag is given the slot's position and size, keeping (or making) a viewBox so the card scales to fit.
+fn place(svg: &str, x: f64, y: f64, w: f64, h: f64) -> String {
+    let start = match svg.find("<svg") {
+        Some(s) => s,
+        None => return format!("<g transform=\"translate({} {})\">{}</g>", x, y, svg),
+    };
+    let end = match svg[start..].find('>') {
+        Some(e) => start + e,
+        None => return String::new(),
+    };
+    let tag = &svg[start..end];
+    let num = |v: Option<&str>, def: f64| {
+        v.map(|v| v.trim_end_matches(|c: char| c.is_alphabetic()))
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(def)
+    };
+    let view_box = match attr(tag, "viewBox") {
+        Some(_) => String::new(),
+        None => format!(
+            " viewBox=\"0 0 {} {}\"",
+            num(attr(tag, "width"), w),
+            num(attr(tag, "height"), h)
+        ),
+    };
+    let mut new_tag = tag.to_string();
+    for a in ["x", "y", "width", "height"] {
+        new_tag = without_attr(&new_tag, a);
+    }
+    format!(
+        "{} x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"{}{}",
+        new_tag,
+        x,
+        y,
+        w,
+        h,
+        view_box,
+        &svg[end..]
+    )
+}
+
+fn strip_prolog(svg: &str) -> &str {
+    match svg.find("<svg") {
+        Some(n) => &svg[n..],
+        None => svg,
+    }
+}
+
+fn cut_marks(layout: &Layout, cols: usize, rows: usize, x0: f64, y0: f64) -> String {
+    let (cw, ch) = layout.cell();
+    let len = (layout.margin * 0.8).min(5.);
+    let (x1, y1) = (x0 + cw * cols as f64, y0 + ch * rows as f64);
+    let mut res = String::new();
+    let mut line = |ax: f64, ay: f64, bx: f64, by: f64| {
+        res.push_str(&format!(
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"black\" stroke-width=\"0.2\"/>\n",
+            ax, ay, bx, by
+        ));
+    };
+    for c in 0..cols {
+        for x in [x0 + cw * c as f64 + layout.bleed, x0 + cw * (c + 1) as f64 - layout.bleed] {
+            line(x, y0 - len, x, y0);
+            line(x, y1, x, y1 + len);
+        }
+    }
+    for r in 0..rows {
+        for y in [y0 + ch * r as f64 + layout.bleed, y0 + ch * (r + 1) as f64 - layout.bleed] {
+            line(x0 - len, y, x0, y);
+            line(x1, y, x1 + len, y);
+        }
+    }
+    res
+}
+
+/// Lay out "num" copies of each card on as many sheets as needed, returning one svg per sheet.
+pub fn render_sheets(cards: &[Card], template: &str, layout: &Layout) -> Vec<String> {
+    let template = strip_prolog(template);
+    let copies: Vec<&Card> = cards
+        .iter()
+        .flat_map(|c| std::iter::repeat(c).take(c.num))
+        .collect();
+    let (cols, rows) = layout.grid();
+    let (cw, ch) = layout.cell();
+    let (pw, ph) = layout.paper;
+    let x0 = (pw - cw * cols as f64) / 2.;
+    let y0 = (ph - ch * rows as f64) / 2.;
+
+    copies
+        .chunks(cols * rows)
+        .map(|page| {
+            let mut res = format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{pw}mm\" height=\"{ph}mm\" viewBox=\"0 0 {pw} {ph}\">\n"
+            );
+            for (i, c) in page.iter().enumerate() {
+                let x = x0 + cw * (i % cols) as f64;
+                let y = y0 + ch * (i / cols) as f64;
+                res.push_str(&place(&fill_template(template, c), x, y, cw, ch));
+                res.push('\n');
+            }
+            if layout.cut_marks {
+                res.push_str(&cut_marks(layout, cols, rows, x0, y0));
+            }
+            res.push_str("</svg>\n");
+            res
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_cards;
+    #[test]
+    pub fn test_render_fills_and_pages() {
+        let cards = parse_cards("@param cost\n5*Archer 3:\n.text:\"a & b\"\n.jobs:[[wood,4]]\n5*Mage 2:").unwrap();
+        let tp = "<?xml version=\"1.0\"?>\n<svg width=\"69mm\" height=\"94mm\"><text>{{name}} {{cost}} {{text}} {{jobs.0.1}}</text></svg>";
+        assert_eq!(
+            fill_template(tp, &cards[0]),
+            tp.replace("{{name}} {{cost}} {{text}} {{jobs.0.1}}", "Archer 3 a &amp; b 4")
+        );
+        let pages = render_sheets(&cards, tp, &Layout::default());
+        assert_eq!(Layout::default().grid(), (2, 3));
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].matches("<text>").count(), 6);
+        assert!(pages[0].contains("viewBox=\"0 0 69 94\""));
+        assert!(!pages[0].contains("69mm"));
+    }
+}
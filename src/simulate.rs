@@ -0,0 +1,106 @@
+use crate::card::Card;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+/// The deck as it would be shuffled, each card repeated num times
+pub fn expand(cards: &[Card]) -> Vec<&Card> {
+    cards
+        .iter()
+        .flat_map(|c| std::iter::repeat(c).take(c.num))
+        .collect()
+}
+
+/// Shuffles and draws from a deck, the same seed always gives the same hands
+pub struct Simulator<'a> {
+    deck: Vec<&'a Card>,
+    rng: StdRng,
+}
+
+impl<'a> Simulator<'a> {
+    pub fn new(cards: &'a [Card], seed: u64) -> Self {
+        Simulator {
+            deck: expand(cards),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn deck_size(&self) -> usize {
+        self.deck.len()
+    }
+
+    /// Shuffle the whole deck and take the top cards
+    pub fn draw(&mut self, hand: usize) -> Vec<&'a Card> {
+        let hand = hand.min(self.deck.len());
+        let (top, _) = self.deck.partial_shuffle(&mut self.rng, hand);
+        top.to_vec()
+    }
+
+    /// The share of trial hands holding at least that many matching cards
+    pub fn monte_carlo<F: Fn(&Card) -> bool>(
+        &mut self,
+        hand: usize,
+        at_least: usize,
+        pred: F,
+        trials: usize,
+    ) -> f64 {
+        if trials == 0 {
+            return 0.;
+        }
+        let hits = (0..trials)
+            .filter(|_| self.draw(hand).iter().filter(|c| pred(c)).count() >= at_least)
+            .count();
+        hits as f64 / trials as f64
+    }
+}
+
+/// How many ways to choose k from n
+pub fn choose(n: usize, k: usize) -> f64 {
+    if k > n {
+        return 0.;
+    }
+    let k = k.min(n - k);
+    (0..k).fold(1., |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+/// The chance of at least `at_least` successes in a hand drawn from a deck of `deck` holding `successes`
+pub fn hypergeometric(deck: usize, successes: usize, hand: usize, at_least: usize) -> f64 {
+    let hand = hand.min(deck);
+    let total = choose(deck, hand);
+    (at_least..=hand.min(successes))
+        .map(|i| choose(successes, i) * choose(deck - successes, hand - i) / total)
+        .sum()
+}
+
+/// The exact chance of drawing at least that many matching cards in a hand
+pub fn exact<F: Fn(&Card) -> bool>(cards: &[Card], hand: usize, at_least: usize, pred: F) -> f64 {
+    let deck = expand(cards);
+    let successes = deck.iter().filter(|c| pred(c)).count();
+    hypergeometric(deck.len(), successes, hand, at_least)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filter::Predicate;
+    use crate::parse_cards;
+    #[test]
+    pub fn test_simulation_matches_exact() {
+        let cards = parse_cards("@param cost\n4*Cheap 1:\n10*Dear 5:\n6*Mid 2:").unwrap();
+        let cheap_pred = Predicate::parse("cost<=2").unwrap();
+        let cheap = |c: &Card| cheap_pred.matches(c);
+        let ex = exact(&cards, 5, 1, cheap);
+        // 1 - C(10,5)/C(20,5)
+        assert!((ex - (1. - 252. / 15504.)).abs() < 1e-9);
+        assert!((hypergeometric(20, 0, 5, 1)).abs() < 1e-12);
+        assert!((hypergeometric(20, 20, 5, 5) - 1.).abs() < 1e-12);
+
+        let mut a = Simulator::new(&cards, 7);
+        assert_eq!(a.deck_size(), 20);
+        let mc = a.monte_carlo(5, 1, cheap, 20000);
+        assert!((mc - ex).abs() < 0.02);
+        let hand_a: Vec<String> = Simulator::new(&cards, 3).draw(5).iter().map(|c| c.name.clone()).collect();
+        let hand_b: Vec<String> = Simulator::new(&cards, 3).draw(5).iter().map(|c| c.name.clone()).collect();
+        assert_eq!(hand_a, hand_b);
+    }
+}
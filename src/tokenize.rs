@@ -1,3 +1,7 @@
+use crate::dice::Dice;
+use crate::interval::Interval;
+use std::borrow::Cow;
+use std::cell::Cell;
 use tokenate::*;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -7,6 +11,8 @@ pub enum CardToken {
     KwParam,
     KwConst,
     KwDef,
+    KwSection,
+    KwInclude,
     Colon,
     Comma,
     Star,
@@ -17,13 +23,19 @@ pub enum CardToken {
     WiggleClose,
     Break,
     Dollar,
+    Amp,
+    Percent,
     //DollarVar(String),
     //DollarNum(usize),
     Text(String),
-    Number(isize),
+    Number(i128),
+    Dice(Dice),
+    Interval(Box<Interval>),
 }
 
-const SPECIAL_CHARS : &str= ",.*:;-[]{}()@ \n\t";
+/// Chars that end a bare word. "$" is one so a path such as ".g$s" is the key s of g, not the key "g$s".
+/// "%" and "&" are not: they only start a tag or reference at the start of a token, so "Tom&Jerry" is one word.
+const SPECIAL_CHARS : &str= ",.*:;-[]{}()@$ \n\t";
 
 impl CardToken {
     pub fn as_text(&self) -> Option<String> {
@@ -32,7 +44,7 @@ impl CardToken {
             _ => None,
         }
     }
-    pub fn as_number(&self) -> Option<isize> {
+    pub fn as_number(&self) -> Option<i128> {
         match self {
             Self::Number(n) => Some(*n),
             _ => None,
@@ -54,8 +66,13 @@ impl CardToken {
     }
 }
 
+/// A character allowed in the language after a property name
+pub fn is_lang_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_'
+}
+
 fn num_digit(c: char) -> bool {
-    c >= '0' && c <= '9'
+    c.is_ascii_digit()
 }
 
 impl CardToken {
@@ -64,18 +81,34 @@ impl CardToken {
             "def" => Some(CardToken::KwDef),
             "param" => Some(CardToken::KwParam),
             "const" => Some(CardToken::KwConst),
+            "section" => Some(CardToken::KwSection),
+            "include" => Some(CardToken::KwInclude),
             _ => None,
         }
     }
 }
 pub struct CardTokenizer<'a> {
     tk: InnerTokenizer<'a>,
+    src: &'a str,
+    owned: bool,
 }
 
 impl<'a> CardTokenizer<'a> {
     pub fn new(s: &'a str) -> Self {
         Self {
             tk: InnerTokenizer::new(s),
+            src: s,
+            owned: true,
+        }
+    }
+
+    /// A tokenizer whose Text tokens are left empty, for callers reading the token's slice instead.
+    /// Use token_text to get the text.
+    pub fn borrowing(s: &'a str) -> Self {
+        Self {
+            tk: InnerTokenizer::new(s),
+            src: s,
+            owned: false,
         }
     }
 
@@ -90,18 +123,163 @@ impl<'a> CardTokenizer<'a> {
         loop {
             match self.tk.next() {
                 Some((_, '"')) => return self.tk.token_res(CardToken::Text(s), true),
-                Some((_, c)) => s.push(c),
+                // '\"' and '\\' are escapes, any other backslash is kept
+                Some((_, '\\')) => match self.tk.peek_char() {
+                    Some(c) if c == '"' || c == '\\' => {
+                        self.tk.unpeek();
+                        if self.owned {
+                            s.push(c);
+                        }
+                    }
+                    _ if self.owned => s.push('\\'),
+                    _ => {}
+                },
+                Some((_, c)) if self.owned => s.push(c),
+                Some(_) => {}
                 None => return self.tk.expected("String to end".to_string()),
             }
         }
     }
 
+    /// A language straight after a property name, eg the "en" of ".text@en"
+    pub fn lang_suffix(&mut self) -> TokenRes<'a, String> {
+        if self.tk.peek_char() != Some('@') {
+            return Ok(None);
+        }
+        self.tk.start_token();
+        self.tk.unpeek();
+        self.tk.take_while(
+            is_lang_char,
+            |s| match s.is_empty() {
+                true => Err("Language".to_string()),
+                false => Ok(s.to_string()),
+            },
+        )
+    }
+
     pub fn number(&mut self) -> TokenRes<'a, usize> {
         self.tk.take_while(num_digit, |s| {
-            Ok(s.parse().map_err(|_| "Could not make number".to_string())?)
+            s.parse().map_err(|_| "Could not make number".to_string())
         })
     }
 
+    /// A bare word, up to the next special char
+    fn word(&mut self) -> TokenRes<'a, CardToken> {
+        let owned = self.owned;
+        self.tk.take_while(
+            |c| !SPECIAL_CHARS.contains(c),
+            |s| match owned {
+                true => Ok(CardToken::Text(s.to_string())),
+                false => Ok(CardToken::Text(String::new())),
+            },
+        )
+    }
+
+    /// The source from the peeked char to the next special char
+    fn rest_of_word(&mut self) -> &'a str {
+        let rest = self.rest();
+        let end = rest.find(|c| SPECIAL_CHARS.contains(c)).unwrap_or(rest.len());
+        &rest[..end]
+    }
+
+    /// The source from the peeked char on
+    fn rest(&mut self) -> &'a str {
+        let i = self.tk.peek_index();
+        &self.src[i..]
+    }
+
+    /// True if the source continues with ".." then a number, as in the "..7" range
+    fn dots_then_number(&mut self) -> bool {
+        let rest = self.rest();
+        let mut chars = rest.chars().skip(2);
+        rest.starts_with("..")
+            && match chars.next() {
+                Some('-') | Some('+') => chars.next().is_some_and(num_digit),
+                c => c.is_some_and(num_digit),
+            }
+    }
+
+    /// A number, dice or range literal, after any sign
+    fn number_lit(&mut self, neg: bool) -> TokenRes<'a, CardToken> {
+        let t = match self.single_lit(neg)? {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+        match (&t.value, self.rest().starts_with("..")) {
+            (CardToken::Number(n), true) => self.range(t.start, Some(*n)),
+            (_, true) => self.tk.expected("Number before '..'".to_string()),
+            _ => Ok(Some(t)),
+        }
+    }
+
+    /// The rest of a range such as "3..5", "..7" or "3..", from its ".."
+    fn range(&mut self, start: Pos, lo: Option<i128>) -> TokenRes<'a, CardToken> {
+        self.tk.next();
+        self.tk.next();
+        let hi = match self.tk.peek_char() {
+            Some(c) if num_digit(c) => self.single_lit(false)?,
+            Some(c @ '-') | Some(c @ '+') => {
+                self.tk.unpeek();
+                match self.tk.peek_char() {
+                    Some(d) if num_digit(d) => self.single_lit(c == '-')?,
+                    _ => return self.tk.expected("Number after sign".to_string()),
+                }
+            }
+            _ => None,
+        };
+        let hi = match hi.map(|t| t.value) {
+            Some(CardToken::Number(n)) => Some(n),
+            Some(_) => return self.tk.expected("Number after '..'".to_string()),
+            None => None,
+        };
+        if let Some(c) = self.tk.peek_char() {
+            if c.is_alphanumeric() || c == '_' || c == '.' {
+                return self.tk.expected("Number or end of range".to_string());
+            }
+        }
+        let iv = match Interval::new(lo, hi) {
+            Some(iv) => iv,
+            None => return self.tk.expected("Range with its start before its end".to_string()),
+        };
+        let end = self.tk.peek_pos();
+        self.tk.start_token();
+        Ok(Some(Token {
+            value: CardToken::Interval(Box::new(iv)),
+            s: &self.src[start.i..end.i],
+            start,
+            end,
+        }))
+    }
+
+    /// A number or dice literal, after any sign
+    fn single_lit(&mut self, neg: bool) -> TokenRes<'a, CardToken> {
+        // dice such as "2d6+1" carry on through one sign, hex such as "0xd" does not
+        let (dice, hex, signed) = (Cell::new(false), Cell::new(false), Cell::new(false));
+        self.tk.take_while(
+            |c: char| match c {
+                '+' | '-' if dice.get() && !hex.get() && !signed.get() => {
+                    signed.set(true);
+                    true
+                }
+                'd' | 'D' => {
+                    dice.set(true);
+                    true
+                }
+                'x' | 'X' => {
+                    hex.set(true);
+                    true
+                }
+                c => c.is_ascii_alphanumeric() || c == '_',
+            },
+            |s| match (Dice::parse(s), neg) {
+                (Some(_), true) => Err("Dice without a '-' sign".to_string()),
+                (Some(d), false) => Ok(CardToken::Dice(d)),
+                (None, _) => parse_number(s, neg).map(CardToken::Number),
+            },
+        )
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> TokenRes<'a, CardToken> {
         self.tk.skip(" \t\r");
         let pc = match self.tk.peek_char() {
@@ -115,11 +293,24 @@ impl<'a> CardTokenizer<'a> {
             '{' => self.tk.token_res(CardToken::WiggleOpen, true),
             '}' => self.tk.token_res(CardToken::WiggleClose, true),
             '*' => self.tk.token_res(CardToken::Star, true),
-            '-' => self.tk.token_res(CardToken::Minus, true),
+            '-' | '+' => {
+                self.tk.unpeek();
+                match self.tk.peek_char() {
+                    Some(c) if num_digit(c) => self.number_lit(pc == '-'),
+                    _ if pc == '-' => self.tk.token_res(CardToken::Minus, false),
+                    _ => self.tk.expected("Number after '+'".to_string()),
+                }
+            }
             ',' => self.tk.token_res(CardToken::Comma, true),
             '\n' | ';' => self.tk.token_res(CardToken::Break, true),
+            '.' if self.dots_then_number() => {
+                let start = self.tk.peek_pos();
+                self.range(start, None)
+            }
             '.' => self.tk.token_res(CardToken::Dot, true),
             '$' => self.tk.token_res(CardToken::Dollar, true),
+            '&' => self.tk.token_res(CardToken::Amp, true),
+            '%' => self.tk.token_res(CardToken::Percent, true),
             /*      '.' => self
             .tk
             .take_while(|c| c == '.', |s| Ok(CardToken::Dots(s.len()))),*/
@@ -147,20 +338,96 @@ impl<'a> CardTokenizer<'a> {
                 self.tk.consume_as("\n;", CardToken::Break).or(Ok(None))
             }
             '"' => self.qoth(),
-            c if c.is_alphabetic() => self
-                .tk
-                .take_while(|c| !SPECIAL_CHARS.contains(c), |s| Ok(CardToken::Text(s.to_string()))),
-            c if num_digit(c) => self.tk.take_while(num_digit, |s| {
-                Ok(CardToken::Number(
-                    s.parse().map_err(|_| "Could not make number".to_string())?,
-                ))
-            }),
+            c if c.is_alphabetic() => self.word(),
+            // "50%" is a word, not the number 50 then a tag
+            c if num_digit(c) && self.rest_of_word().contains(['%', '&']) => self.word(),
+            c if num_digit(c) => self.number_lit(false),
 
             _ => self.tk.expected("Something else".to_string()),
         }
     }
 }
 
+/// The lowest and highest numbers, so both i64 values and u64 bit flags can be written
+pub const NUMBER_MIN: i128 = i64::MIN as i128;
+pub const NUMBER_MAX: i128 = u64::MAX as i128;
+
+/// None if the number is outside NUMBER_MIN..=NUMBER_MAX
+pub fn in_number_range(n: i128) -> Option<i128> {
+    Some(n).filter(|n| (NUMBER_MIN..=NUMBER_MAX).contains(n))
+}
+
+/// The value of a number literal without its sign, eg "12", "1_000", "0xff" or "0b1010".
+/// The number, with its sign, must be from NUMBER_MIN to NUMBER_MAX whatever the target and base,
+/// so "0xffff_ffff_ffff_ffff" is u64::MAX rather than -1, and one more is an error.
+pub fn parse_number(s: &str, neg: bool) -> Result<i128, String> {
+    let (digits, radix) = match s.get(..2) {
+        Some("0x") | Some("0X") => (&s[2..], 16),
+        Some("0b") | Some("0B") => (&s[2..], 2),
+        _ => (s, 10),
+    };
+    let valid = digits.chars().all(|c| c == '_' || c.is_digit(radix));
+    if !valid || digits.is_empty() || digits.starts_with('_') || digits.ends_with('_') {
+        return Err("Number, eg 12, 1_000, 0xff or 0b101".to_string());
+    }
+    let out_of_range = || format!("Number from {} to {}", NUMBER_MIN, NUMBER_MAX);
+    let mag = u64::from_str_radix(&digits.replace('_', ""), radix).map_err(|_| out_of_range())?;
+    let n = if neg { -i128::from(mag) } else { i128::from(mag) };
+    in_number_range(n).ok_or_else(out_of_range)
+}
+
+/// The text of a Text token, taken from the source without its quotes.
+/// It is only copied if it contains escapes.
+pub fn token_text<'a>(t: &Token<'a, CardToken>) -> Cow<'a, str> {
+    // the slice can begin with the spaces skipped before the token
+    let s = t.s.trim_start();
+    match s.strip_prefix('"') {
+        Some(q) => unescape(q.strip_suffix('"').unwrap_or(q)),
+        None => Cow::Borrowed(s),
+    }
+}
+
+/// The inside of a quoted string with its escapes replaced
+pub fn unescape(s: &str) -> Cow<'_, str> {
+    if !s.contains('\\') {
+        return Cow::Borrowed(s);
+    }
+    let mut res = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(&n)) if n == '"' || n == '\\' => {
+                chars.next();
+                res.push(n);
+            }
+            (c, _) => res.push(c),
+        }
+    }
+    Cow::Owned(res)
+}
+
+/// s quoted, so the tokenizer reads it back as one Text token
+pub fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// True if s can be written without quotes, as a name, key or tag
+pub fn is_word(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() => chars.all(|c| c.is_alphanumeric() || c == '_'),
+        _ => false,
+    }
+}
+
+/// s as a name, key or tag, only quoted if it needs to be
+pub fn word(s: &str) -> Cow<'_, str> {
+    match is_word(s) {
+        true => Cow::Borrowed(s),
+        false => Cow::Owned(quote(s)),
+    }
+}
+
 #[cfg(test)]
 mod token_tests {
     use super::*;
@@ -173,5 +440,55 @@ mod token_tests {
         let nx = tk.next().unwrap().unwrap();
         assert_eq!(nx.value, CardToken::KwConst);
         assert!(tk.next().is_err());
+        let mut tk = CardTokenizer::new("Archer %core x&y &z 50% Tom&Jerry");
+        let mut res = Vec::new();
+        while let Some(t) = tk.next().unwrap() {
+            res.push(t.value);
+        }
+        let text = |s: &str| CardToken::Text(s.to_string());
+        let want = [
+            text("Archer"),
+            CardToken::Percent,
+            text("core"),
+            text("x&y"),
+            CardToken::Amp,
+            text("z"),
+            text("50%"),
+            text("Tom&Jerry"),
+        ];
+        assert_eq!(res, want);
+        let mut tk = CardTokenizer::new("g$s");
+        assert_eq!(tk.next().unwrap().unwrap().value, text("g"));
+        assert_eq!(tk.next().unwrap().unwrap().value, CardToken::Dollar);
+    }
+    #[test]
+    pub fn test_number_literals() {
+        let nums = |s: &str| {
+            let mut tk = CardTokenizer::new(s);
+            let mut res = Vec::new();
+            while let Some(t) = tk.next()? {
+                res.push(t.value.as_number().unwrap());
+            }
+            Ok::<_, TErr>(res)
+        };
+        assert_eq!(nums("12 +3 -4 1_000 0xff 0b1010 -0x10 0XFF_FF").unwrap(), vec![12, 3, -4, 1000, 255, 10, -16, 0xffff]);
+        let (min, max) = (NUMBER_MIN, NUMBER_MAX);
+        let limits = format!("{} {} {:#x} -{:#x} 0xffff_ffff_ffff_ffff", min, max, max, min.unsigned_abs());
+        assert_eq!(nums(&limits).unwrap(), vec![min, max, max, min, u64::MAX as i128]);
+        let above = max + 1;
+        let below = min.unsigned_abs() + 1;
+        let out_of_range = [
+            above.to_string(),
+            format!("-{}", below),
+            format!("{:#x}", above),
+            format!("{:#b}", above),
+            format!("{:#x}", u128::MAX),
+            format!("-{:#x}", below),
+        ];
+        let malformed = ["1__", "0x_1", "0x", "0b12", "12ab", "+"].map(String::from);
+        for bad in out_of_range.iter().chain(&malformed) {
+            let e = nums(bad).unwrap_err();
+            assert!(e.exp.starts_with("Number"), "{}: {:?}", bad, e);
+        }
     }
 }